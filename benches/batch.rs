@@ -0,0 +1,53 @@
+//! Calculation of formulas over columns row by row with `Vm` and in chunks with `BatchVm`.
+//! Run with `cargo bench --bench batch`.
+
+#![allow(clippy::needless_return)]
+
+use expression_declarator::bytecode::{BatchVm, Vm};
+use expression_declarator::f64_calculator::f64_calculator;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const FORMULAS: [&str; 3] = [
+    "x * 2 + y",
+    "sin(x) ^ 2 + cos(y) ^ 2 - sqrt(abs(x * y)) / (1 + exp(-x))",
+    "max(x, y) * log(2, 1 + x ^ 2) + hypot(x, y) % 3 - atan2(y, x) * 180 / pi",
+];
+const ROWS: usize = 1_000_000;
+
+fn main() {
+    let mut calculator = f64_calculator();
+    calculator.add_variable("x".to_string());
+    calculator.add_variable("y".to_string());
+    let xs: Vec<f64> = (0..ROWS).map(|it| it as f64 * 0.001).collect();
+    let ys: Vec<f64> = (0..ROWS).map(|it| (ROWS - it) as f64 * 0.002).collect();
+    let mut out = vec![0.0; ROWS];
+
+    for formula in FORMULAS {
+        let ast = calculator.build_ast(formula).ok().unwrap();
+        let program = calculator.compile(&ast).ok().unwrap();
+
+        let mut vm = Vm::new();
+        let rows = measure(|| {
+            for row in 0..ROWS {
+                out[row] = vm.run(&program, &calculator, &[xs[row], ys[row]]).ok().unwrap();
+            }
+            black_box(&out);
+        });
+
+        let mut batch = BatchVm::new();
+        let columns = measure(|| {
+            batch.run(&program, &calculator, &[&xs, &ys], &mut out).ok().unwrap();
+            black_box(&out);
+        });
+
+        println!("{:<75} Vm {:>9.2?}, BatchVm {:>9.2?} per {} rows", formula, rows, columns, ROWS);
+    }
+}
+
+fn measure(mut run: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    run();
+
+    return start.elapsed();
+}
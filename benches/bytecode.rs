@@ -0,0 +1,52 @@
+//! Repeated calculation of formulas by tree walking of `AstNode` and by stack machine.
+//! Run with `cargo bench --bench bytecode`.
+
+#![allow(clippy::needless_return)]
+
+use expression_declarator::bytecode::Vm;
+use expression_declarator::f64_calculator::f64_calculator;
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const FORMULAS: [&str; 3] = [
+    "x * 2 + y",
+    "sin(x) ^ 2 + cos(y) ^ 2 - sqrt(abs(x * y)) / (1 + exp(-x))",
+    "max(x, y) * log(2, 1 + x ^ 2) + hypot(x, y) % 3 - atan2(y, x) * 180 / pi",
+];
+const ITERATIONS: u32 = 100000;
+
+fn main() {
+    let mut calculator = f64_calculator();
+    calculator.add_variable("x".to_string());
+    calculator.add_variable("y".to_string());
+
+    for formula in FORMULAS {
+        let ast = calculator.build_ast(formula).ok().unwrap();
+        let program = calculator.compile(&ast).ok().unwrap();
+
+        let mut variables = HashMap::new();
+        variables.insert("x".to_string(), 0.0);
+        variables.insert("y".to_string(), 0.5);
+        let tree = measure(|i| {
+            *variables.get_mut("x").unwrap() = i as f64;
+            black_box(ast.calculate_with(&variables).ok().unwrap());
+        });
+
+        let mut vm = Vm::new();
+        let machine = measure(|i| {
+            black_box(vm.run(&program, &calculator, &[i as f64, 0.5]).ok().unwrap());
+        });
+
+        println!("{:<75} AstNode {:>9.2?}, Vm {:>9.2?}", formula, tree, machine);
+    }
+}
+
+fn measure(mut run: impl FnMut(u32)) -> Duration {
+    let start = Instant::now();
+    for i in 0..ITERATIONS {
+        run(i);
+    }
+
+    return start.elapsed() / ITERATIONS;
+}
@@ -0,0 +1,51 @@
+//! Calculation of generated formulas with repeated subexpressions as tree and as DAG after elimination of common subexpressions.
+//! Run with `cargo bench --bench cse`.
+
+#![allow(clippy::needless_return)]
+
+use expression_declarator::flat_ast::FlatAst;
+use expression_declarator::f64_calculator::f64_calculator;
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const TERMS: [usize; 3] = [10, 100, 1000];
+const ITERATIONS: u32 = 1000;
+
+fn main() {
+    let mut calculator = f64_calculator();
+    calculator.add_variable("x".to_string());
+    let mut variables = HashMap::new();
+    variables.insert("x".to_string(), 0.5);
+
+    for terms in TERMS {
+        let input = (0..terms)
+            .map(|i| format!("sin(x + {})^2 * exp(-x / {}) + sin(x + {})*cos(x)", i % 10, i % 3 + 1, i % 10))
+            .collect::<Vec<String>>()
+            .join(" + ");
+        let ast = calculator.build_ast(&input).ok().unwrap();
+        let tree = FlatAst::from_ast(&ast, &calculator).ok().unwrap();
+        let dag = calculator.eliminate_common_subexpressions(&ast).ok().unwrap();
+
+        let tree_time = measure(|| {
+            black_box(tree.calculate_with(&calculator, &variables).ok().unwrap());
+        });
+        let dag_time = measure(|| {
+            black_box(dag.calculate_with(&calculator, &variables).ok().unwrap());
+        });
+
+        println!(
+            "{:>5} terms: tree {:>6} nodes {:>10.2?}, DAG {:>5} nodes {:>10.2?}",
+            terms, tree.node_count(), tree_time, dag.node_count(), dag_time,
+        );
+    }
+}
+
+fn measure(mut run: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        run();
+    }
+
+    return start.elapsed() / ITERATIONS;
+}
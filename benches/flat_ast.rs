@@ -0,0 +1,43 @@
+//! Building, calculation and dropping of boxed `AstNode` and `FlatAst` for generated expressions.
+//! Run with `cargo bench --bench flat_ast`.
+
+#![allow(clippy::needless_return)]
+
+use expression_declarator::f64_calculator::f64_calculator;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const TERMS: [usize; 3] = [1000, 10000, 50000];
+
+fn main() {
+    let calculator = f64_calculator();
+
+    for terms in TERMS {
+        let input = (0..terms)
+            .map(|i| format!("{}.5 * sin({}) - {} ^ 2 / 8", i % 10, i % 7, i % 5))
+            .collect::<Vec<String>>()
+            .join(" + ");
+        let tokens = calculator.tokenize(&input).ok().unwrap();
+        let iterations = (200000 / terms).max(1) as u32;
+
+        let boxed = measure(iterations, || {
+            let ast = calculator.build_ast_from_tokens(black_box(&tokens)).ok().unwrap();
+            black_box(ast.calculate().ok().unwrap());
+        });
+        let flat = measure(iterations, || {
+            let ast = calculator.build_flat_ast_from_tokens(black_box(&tokens)).ok().unwrap();
+            black_box(ast.calculate(&calculator).ok().unwrap());
+        });
+
+        println!("{:>6} terms: AstNode {:>10.2?}, FlatAst {:>10.2?}", terms, boxed, flat);
+    }
+}
+
+fn measure(iterations: u32, mut run: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        run();
+    }
+
+    return start.elapsed() / iterations;
+}
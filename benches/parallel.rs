@@ -0,0 +1,55 @@
+//! Monte Carlo estimation of pi by calculation of a formula over random points in one thread and in parallel.
+//! Run with `cargo bench --bench parallel --features parallel`.
+
+#![allow(clippy::needless_return)]
+
+use expression_declarator::bytecode::Vm;
+use expression_declarator::f64_calculator::f64_calculator;
+use std::hint::black_box;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const FORMULA: &str = "1 - floor(min(x ^ 2 + y ^ 2, 1))";
+const POINTS: usize = 2_000_000;
+
+fn main() {
+    let mut calculator = f64_calculator();
+    calculator.add_variable("x".to_string());
+    calculator.add_variable("y".to_string());
+    let program = calculator.compile(&calculator.build_ast(FORMULA).ok().unwrap()).ok().unwrap();
+
+    let mut random = Random(42);
+    let bindings: Vec<Vec<f64>> = (0..POINTS).map(|_| vec![random.next(), random.next()]).collect();
+
+    let mut vm = Vm::new();
+    let (inside, single) = measure(|| {
+        return bindings.iter().map(|set| vm.run(&program, &calculator, set).ok().unwrap()).sum();
+    });
+    println!("1 thread    pi ~ {:.5} in {:>9.2?}", 4.0 * inside / POINTS as f64, single);
+
+    let available = thread::available_parallelism().map_or(1, |it| it.get());
+    for threads in [2, 4, available] {
+        let (inside, parallel) = measure(|| {
+            return program.calculate_parallel(&calculator, &bindings, threads).ok().unwrap().iter().sum();
+        });
+        println!("{:<2} threads  pi ~ {:.5} in {:>9.2?}", threads, 4.0 * inside / POINTS as f64, parallel);
+    }
+}
+
+fn measure(run: impl FnOnce() -> f64) -> (f64, Duration) {
+    let start = Instant::now();
+    let result = black_box(run());
+
+    return (result, start.elapsed());
+}
+
+/// Linear congruential generator of values in [0, 1)
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> f64 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+
+        return (self.0 >> 11) as f64 / (1u64 << 53) as f64;
+    }
+}
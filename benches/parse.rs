@@ -0,0 +1,50 @@
+//! Tokenization and AST construction time of generated expressions of growing size.
+//! Time per term should stay the same, as both stages are linear in input size.
+//! Run with `cargo bench --bench parse`.
+
+#![allow(clippy::needless_return)]
+
+use expression_declarator::f64_calculator::f64_calculator;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const TERMS: [usize; 4] = [1000, 5000, 20000, 50000];
+
+fn main() {
+    let calculator = f64_calculator();
+
+    for terms in TERMS {
+        let input = expression(terms);
+        let iterations = (200000 / terms).max(1) as u32;
+
+        let tokenize = measure(iterations, || {
+            black_box(calculator.tokenize(black_box(&input)).ok().unwrap());
+        });
+        let tokens = calculator.tokenize(&input).ok().unwrap();
+        let build = measure(iterations, || {
+            black_box(calculator.build_ast_from_tokens(black_box(&tokens)).ok().unwrap());
+        });
+
+        println!(
+            "{:>6} terms, {:>8} chars: tokenize {:>10.2?} ({:>7.2?} per term), build AST {:>10.2?} ({:>7.2?} per term)",
+            terms, input.len(), tokenize, tokenize / terms as u32, build, build / terms as u32,
+        );
+    }
+}
+
+/// Sum of terms like `2.5 * sin(3) - 4 ^ 2 / 8`
+fn expression(terms: usize) -> String {
+    return (0..terms)
+        .map(|i| format!("{}.5 * sin({}) - {} ^ 2 / 8", i % 10, i % 7, i % 5))
+        .collect::<Vec<String>>()
+        .join(" + ");
+}
+
+fn measure(iterations: u32, mut run: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        run();
+    }
+
+    return start.elapsed() / iterations;
+}
@@ -0,0 +1,26 @@
+//! Tokenization time of the same expression with growing number of declared functions.
+//! Run with `cargo bench --bench tokenize`.
+
+use expression_declarator::f64_calculator::f64_calculator;
+use std::hint::black_box;
+use std::time::Instant;
+
+const EXPRESSION: &str = "sin(pi / 4) * 2 ^ 10 + max(log(2, 1024), sqrt 144) - 3! % 5 + fn0(1) + fn9(2)";
+const ITERATIONS: u32 = 2000;
+
+fn main() {
+    for count in [10, 100, 1000, 10000] {
+        let mut calculator = f64_calculator();
+        for i in 0..count {
+            calculator.add_function(format!("fn{}", i), "Identity".to_string(), Box::new(|ops: &[f64]| ops[0]), 1);
+        }
+
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            black_box(calculator.tokenize(black_box(EXPRESSION)).ok().unwrap());
+        }
+        let elapsed = start.elapsed() / ITERATIONS;
+
+        println!("{:>6} functions: {:>10.2?} per tokenize", count, elapsed);
+    }
+}
@@ -1,30 +1,384 @@
-use crate::token::Token;
-use crate::operation::Operation;
+use crate::token::{Span, Token};
+use crate::operation::{Associativity, Operation, OperationType};
+use crate::declarator::{ExpressionDeclarator, ULTIMATE_ORDER};
+use crate::calculator::{BoxedThunk, Executor, Thunk};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 
 pub enum AstNode<T: Clone> {
     Primitive { val: T, token: Token<T> },
-    Unary { op: Box<Operation<T>>, p1: Box<AstNode<T>>, token: Token<T> },
-    Binary { op: Box<Operation<T>>, p1: Box<AstNode<T>>, p2: Box<AstNode<T>>, token: Token<T> },
+    Unary { op: Arc<Operation<T>>, p1: Box<AstNode<T>>, token: Token<T> },
+    Binary { op: Arc<Operation<T>>, p1: Box<AstNode<T>>, p2: Box<AstNode<T>>, token: Token<T> },
+    Nary { op: Arc<Operation<T>>, args: Vec<AstNode<T>>, token: Token<T> },
+    Variable { name: String, token: Token<T> },
 }
 
+/// Cloned bottom-up with explicit stack, so deep trees don't overflow the call stack
 impl<T: 'static + Clone> Clone for AstNode<T> {
     fn clone(&self) -> Self {
-        return match self {
-            AstNode::Primitive { val, token } => AstNode::Primitive { val: val.clone(), token: token.clone() },
-            AstNode::Unary { op, p1, token } => AstNode::Unary { op: op.clone(), p1: p1.clone(), token: token.clone() },
-            AstNode::Binary { op, p1, p2, token } => AstNode::Binary { op: op.clone(), p1: p1.clone(), p2: p2.clone(), token: token.clone() },
-        };
+        let mut frames: Vec<(&AstNode<T>, usize)> = vec![(self, 0)];
+        let mut copies: Vec<AstNode<T>> = Vec::new();
+
+        while let Some((node, next)) = frames.pop() {
+            if next < node.arity() {
+                frames.push((node, next + 1));
+                frames.push((node.child(next), 0));
+                continue;
+            }
+
+            let mut children = copies.split_off(copies.len() - node.arity());
+            let copy = match node {
+                AstNode::Primitive { val, token } => AstNode::Primitive { val: val.clone(), token: token.clone() },
+                AstNode::Variable { name, token } => AstNode::Variable { name: name.clone(), token: token.clone() },
+                AstNode::Unary { op, token, .. } => AstNode::Unary { op: op.clone(), p1: Box::new(children.pop().unwrap()), token: token.clone() },
+                AstNode::Binary { op, token, .. } => {
+                    let p2 = Box::new(children.pop().unwrap());
+                    AstNode::Binary { op: op.clone(), p1: Box::new(children.pop().unwrap()), p2, token: token.clone() }
+                }
+                AstNode::Nary { op, token, .. } => AstNode::Nary { op: op.clone(), args: children, token: token.clone() },
+            };
+            copies.push(copy);
+        }
+
+        return copies.pop().unwrap();
+    }
+}
+
+/// Children are detached and dropped one by one, so deep trees don't overflow the call stack
+impl<T: Clone> Drop for AstNode<T> {
+    fn drop(&mut self) {
+        let mut detached: Vec<AstNode<T>> = Vec::new();
+        detach_children(self, &mut detached);
+        while let Some(mut node) = detached.pop() {
+            detach_children(&mut node, &mut detached);
+        }
+    }
+}
+
+/// Trees are equal, when they have the same shape, primitives with the same text, variables with the same names
+/// and operations with the same signature and type, as executors can't be compared. Values of primitives aren't compared,
+/// so `2` and `2.0` are different trees.
+impl<T: 'static + Clone> PartialEq for AstNode<T> {
+    fn eq(&self, other: &Self) -> bool {
+        return self.keys().eq(other.keys());
+    }
+}
+
+impl<T: 'static + Clone> Eq for AstNode<T> {}
+
+/// Hash of the same keys of nodes, which are compared by equality
+impl<T: 'static + Clone> Hash for AstNode<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for key in self.keys() {
+            key.hash(state);
+        }
+    }
+}
+
+/// Part of node, which is compared and hashed, without its operands
+#[derive(PartialEq, Eq, Hash)]
+enum NodeKey<'a> {
+    Primitive(&'a str),
+    /// Primitive, which is a value of constant operation
+    Constant(&'a str, &'a OperationType),
+    Variable(&'a str),
+    Operation(&'a str, &'a OperationType, usize),
+}
+
+/// Move children of node to the list, leaving leaves without allocations in their place
+fn detach_children<T: Clone>(node: &mut AstNode<T>, detached: &mut Vec<AstNode<T>>) {
+    let mut detach = |child: &mut Box<AstNode<T>>| {
+        if matches!(**child, AstNode::Unary { .. } | AstNode::Binary { .. } | AstNode::Nary { .. }) {
+            let leaf = AstNode::Variable { name: String::new(), token: Token::Unknown { span: Span::default(), val: String::new() } };
+            detached.push(std::mem::replace(&mut **child, leaf));
+        }
+    };
+
+    match node {
+        AstNode::Unary { p1, .. } => detach(p1),
+        AstNode::Binary { p1, p2, .. } => {
+            detach(p1);
+            detach(p2);
+        }
+        AstNode::Nary { args, .. } => detached.append(args),
+        _ => (),
     }
 }
 
 impl<T: 'static + Clone> AstNode<T> {
+    /// Calculate AST without variables. Variable is reported as an error.
     pub fn calculate(&self) -> Result<T, Token<T>> {
-        let result = match self {
-            AstNode::Primitive { val, .. } => val.clone(),
-            AstNode::Unary { op, p1, .. } => (&op.executor)(vec![p1.calculate()?]),
-            AstNode::Binary { op, p1, p2, .. } => (&op.executor)(vec![p1.calculate()?, p2.calculate()?]),
+        return self.calculate_with(&HashMap::new());
+    }
+
+    /// Calculate AST with values of variables. Unknown variable is reported as an error.
+    /// Operands of eager operations are calculated with explicit stack, so the depth of AST is limited by memory only.
+    /// Lazy operations calculate their operands by nested calls, each one takes the call stack.
+    pub fn calculate_with(&self, variables: &HashMap<String, T>) -> Result<T, Token<T>> {
+        let mut frames: Vec<(&AstNode<T>, usize)> = vec![(self, 0)];
+        let mut values: Vec<T> = Vec::new();
+
+        while let Some((node, next)) = frames.pop() {
+            let op = match node {
+                AstNode::Primitive { val, .. } => {
+                    values.push(val.clone());
+                    continue;
+                }
+                AstNode::Variable { name, token } => match variables.get(name) {
+                    Some(val) => {
+                        values.push(val.clone());
+                        continue;
+                    }
+                    None => return Result::Err(token.clone()),
+                },
+                AstNode::Unary { op, .. } | AstNode::Binary { op, .. } | AstNode::Nary { op, .. } => op,
+            };
+
+            match &op.executor {
+                Executor::Eager(_) if next < node.arity() => {
+                    frames.push((node, next + 1));
+                    frames.push((node.child(next), 0));
+                }
+                Executor::Eager(executor) => {
+                    let first = values.len() - node.arity();
+                    let value = executor(&values[first..]);
+                    values.truncate(first);
+                    values.push(value);
+                }
+                Executor::Lazy(_) => {
+                    let operands: Vec<&AstNode<T>> = (0..node.arity()).map(|index| node.child(index)).collect();
+                    values.push(apply(op, &operands, variables)?);
+                }
+            }
+        }
+
+        return Result::Ok(values.pop().unwrap());
+    }
+
+    /// Token, from which node was built
+    pub fn token(&self) -> &Token<T> {
+        return match self {
+            AstNode::Primitive { token, .. } => token,
+            AstNode::Unary { token, .. } => token,
+            AstNode::Binary { token, .. } => token,
+            AstNode::Nary { token, .. } => token,
+            AstNode::Variable { token, .. } => token,
         };
+    }
+
+    /// Names of variables in order of the first appearance
+    pub fn variables(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        let mut nodes = vec![self];
+        while let Some(node) = nodes.pop() {
+            if let AstNode::Variable { name, .. } = node {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+            nodes.extend((0..node.arity()).rev().map(|index| node.child(index)));
+        }
+
+        return names;
+    }
+
+    /// Number of nodes on the longest path from the root to a leaf
+    pub fn depth(&self) -> usize {
+        let mut deepest = 0;
+        let mut nodes = vec![(self, 1)];
+        while let Some((node, depth)) = nodes.pop() {
+            deepest = deepest.max(depth);
+            nodes.extend((0..node.arity()).map(|index| (node.child(index), depth + 1)));
+        }
 
-        return Result::Ok(result);
+        return deepest;
     }
-}
\ No newline at end of file
+
+    /// Number of lazy operations on the longest path from the root to a leaf, as limited by `set_max_depth`
+    pub fn lazy_depth(&self) -> usize {
+        let mut deepest = 0;
+        let mut nodes = vec![(self, 0)];
+        while let Some((node, depth)) = nodes.pop() {
+            let depth = match node {
+                AstNode::Unary { op, .. } | AstNode::Binary { op, .. } | AstNode::Nary { op, .. } if matches!(op.executor, Executor::Lazy(_)) => depth + 1,
+                _ => depth,
+            };
+            deepest = deepest.max(depth);
+            nodes.extend((0..node.arity()).map(|index| (node.child(index), depth)));
+        }
+
+        return deepest;
+    }
+
+    /// Keys of nodes in pre-order with explicit stack. With arities of operations they define the tree.
+    fn keys(&self) -> impl Iterator<Item = NodeKey<'_>> {
+        let mut nodes = vec![self];
+
+        return std::iter::from_fn(move || {
+            let node = nodes.pop()?;
+            nodes.extend((0..node.arity()).rev().map(|index| node.child(index)));
+
+            return Some(match node {
+                AstNode::Primitive { token: Token::Operation { val, .. }, .. } => NodeKey::Constant(&val.signature, &val.op_type),
+                AstNode::Primitive { token, .. } => NodeKey::Primitive(token.text()),
+                AstNode::Variable { name, .. } => NodeKey::Variable(name),
+                AstNode::Unary { op, .. } | AstNode::Binary { op, .. } | AstNode::Nary { op, .. } => {
+                    NodeKey::Operation(&op.signature, &op.op_type, node.arity())
+                }
+            });
+        });
+    }
+
+    /// Number of operands of the node
+    fn arity(&self) -> usize {
+        return match self {
+            AstNode::Primitive { .. } | AstNode::Variable { .. } => 0,
+            AstNode::Unary { .. } => 1,
+            AstNode::Binary { .. } => 2,
+            AstNode::Nary { args, .. } => args.len(),
+        };
+    }
+
+    fn child(&self, index: usize) -> &AstNode<T> {
+        return match self {
+            AstNode::Unary { p1, .. } => p1,
+            AstNode::Binary { p1, .. } if index == 0 => p1,
+            AstNode::Binary { p2, .. } => p2,
+            AstNode::Nary { args, .. } => &args[index],
+            _ => panic!("Leaf has no operands"),
+        };
+    }
+
+    /// Expression string of AST with minimal parenthesis, which can be parsed back by declarator
+    /// with the default separator of arguments
+    pub fn pretty_print(&self) -> String {
+        return self.print(", ");
+    }
+
+    /// Expression string of AST with minimal parenthesis and separator of arguments of the declarator,
+    /// which can be parsed back by it
+    pub fn pretty_print_with(&self, declarator: &ExpressionDeclarator<T>) -> String {
+        return self.print(&format!("{} ", declarator.separator));
+    }
+
+    /// Printed in order with explicit stack of pieces, so deep trees don't overflow the call stack
+    fn print(&self, separator: &str) -> String {
+        let mut result = String::new();
+        let mut pieces: Vec<Piece<T>> = vec![Piece::Node(self, false)];
+
+        while let Some(piece) = pieces.pop() {
+            let (node, parenthesized) = match piece {
+                Piece::Text(text) => {
+                    result.push_str(text);
+                    continue;
+                }
+                Piece::Node(node, parenthesized) => (node, parenthesized),
+            };
+            if parenthesized {
+                pieces.extend([Piece::Text(")"), Piece::Node(node, false), Piece::Text("(")]);
+                continue;
+            }
+
+            let next: Vec<Piece<T>> = match node {
+                AstNode::Primitive { token, .. } => {
+                    result.push_str(&token.get_value());
+                    continue;
+                }
+                AstNode::Variable { name, .. } => {
+                    result.push_str(name);
+                    continue;
+                }
+                AstNode::Unary { op, p1, .. } => match op.op_type {
+                    OperationType::Postfix => vec![Piece::Node(p1, p1.priority() < op.priority), Piece::Text(&op.signature)],
+                    _ if op.signature.chars().all(|it| it.is_alphanumeric()) => vec![Piece::Text(&op.signature), Piece::Node(p1, true)],
+                    _ => vec![Piece::Text(&op.signature), Piece::Node(p1, p1.priority() < op.priority)],
+                },
+                AstNode::Binary { op, p1, p2, .. } if op.is_function() => vec![
+                    Piece::Text(&op.signature),
+                    Piece::Text("("),
+                    Piece::Node(p1, false),
+                    Piece::Text(separator),
+                    Piece::Node(p2, false),
+                    Piece::Text(")"),
+                ],
+                AstNode::Binary { op, p1, p2, .. } => {
+                    let left = match op.associativity {
+                        Associativity::Left => p1.priority() < op.priority,
+                        Associativity::Right => p1.priority() <= op.priority,
+                    };
+                    let right = match op.associativity {
+                        Associativity::Left => p2.priority() <= op.priority,
+                        Associativity::Right => p2.priority() < op.priority,
+                    };
+                    vec![Piece::Node(p1, left), Piece::Text(" "), Piece::Text(&op.signature), Piece::Text(" "), Piece::Node(p2, right)]
+                }
+                AstNode::Nary { op, args, .. } if op.op_type == OperationType::Ternary => vec![
+                    Piece::Node(&args[0], args[0].priority() <= op.priority),
+                    Piece::Text(" "),
+                    Piece::Text(&op.signature),
+                    Piece::Text(" "),
+                    Piece::Node(&args[1], args[1].priority() <= op.priority),
+                    Piece::Text(" "),
+                    Piece::Text(op.delimiter.as_deref().unwrap_or_default()),
+                    Piece::Text(" "),
+                    Piece::Node(&args[2], args[2].priority() < op.priority),
+                ],
+                AstNode::Nary { op, args, .. } => {
+                    let mut next = vec![Piece::Text(&op.signature), Piece::Text("(")];
+                    for (index, arg) in args.iter().enumerate() {
+                        if index > 0 {
+                            next.push(Piece::Text(separator));
+                        }
+                        next.push(Piece::Node(arg, false));
+                    }
+                    next.push(Piece::Text(")"));
+                    next
+                }
+            };
+            pieces.extend(next.into_iter().rev());
+        }
+
+        return result;
+    }
+
+    /// Priority of the top operation of the node
+    fn priority(&self) -> u8 {
+        return match self {
+            AstNode::Unary { op, .. } | AstNode::Binary { op, .. } => op.priority,
+            AstNode::Nary { op, .. } if op.op_type == OperationType::Ternary => op.priority,
+            _ => ULTIMATE_ORDER,
+        };
+    }
+}
+
+/// Part of printed expression: text or node, which is parenthesized if needed
+enum Piece<'a, T: Clone> {
+    Text(&'a str),
+    Node(&'a AstNode<T>, bool),
+}
+
+/// Execute operation over operands.
+/// Operands are calculated before the call for eager executor and on demand for lazy one.
+pub(crate) fn apply<T: 'static + Clone>(
+    op: &Operation<T>,
+    operands: &[&AstNode<T>],
+    variables: &HashMap<String, T>,
+) -> Result<T, Token<T>> {
+    return match &op.executor {
+        Executor::Eager(executor) => {
+            let mut values = Vec::with_capacity(operands.len());
+            for operand in operands {
+                values.push(operand.calculate_with(variables)?);
+            }
+            Result::Ok(executor(&values))
+        }
+        Executor::Lazy(executor) => {
+            let thunks: Vec<BoxedThunk<T>> = operands.iter()
+                .map(|operand| Box::new(move || operand.calculate_with(variables)) as BoxedThunk<T>)
+                .collect();
+            let refs: Vec<Thunk<T>> = thunks.iter().map(|it| it.as_ref()).collect();
+            executor(&refs)
+        }
+    };
+}
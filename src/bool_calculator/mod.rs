@@ -1,51 +1,153 @@
-use crate::declarator::{ExpressionDeclarator, HIGH_ORDER, LOW_ORDER, PrimitiveHandler};
+pub mod truth_table;
+pub mod normal_form;
+pub mod satisfiability;
 
-struct BoolHandler {}
+use crate::calculator::Thunk;
+use crate::declarator::{ExpressionDeclarator, HIGHEST_ORDER, HIGH_ORDER, LOWEST_ORDER, LOW_ORDER, MEDIUM_ORDER, PrimitiveHandler};
+use crate::operation::OperationType;
+
+pub const EQUIVALENCE_ORDER: u8 = LOWEST_ORDER;
+pub const IMPLICATION_ORDER: u8 = LOW_ORDER;
+pub const OR_ORDER: u8 = MEDIUM_ORDER;
+pub const XOR_ORDER: u8 = MEDIUM_ORDER + 5;
+pub const AND_ORDER: u8 = HIGH_ORDER;
+pub const NOT_ORDER: u8 = HIGHEST_ORDER;
+
+/// Boolean primitives. Accepts `true`/`false` and `1`/`0` by default, case insensitive.
+pub struct BoolHandler {
+    literals: Vec<(String, bool)>,
+}
+
+impl BoolHandler {
+    pub fn new() -> BoolHandler {
+        return BoolHandler { literals: Vec::new() }
+            .with_literals("true", "false")
+            .with_literals("1", "0");
+    }
+
+    /// Accept additional literals, e.g. `T`/`F` or `yes`/`no`
+    pub fn with_literals(mut self, true_literal: &str, false_literal: &str) -> BoolHandler {
+        self.literals.push((true_literal.to_lowercase(), true));
+        self.literals.push((false_literal.to_lowercase(), false));
+
+        return self;
+    }
+}
+
+impl Default for BoolHandler {
+    fn default() -> Self {
+        return BoolHandler::new();
+    }
+}
 
 impl PrimitiveHandler<bool> for BoolHandler {
     fn from_string(&self, input: &String) -> Result<bool, ()> {
-        return match input.to_lowercase().as_str() {
-            "true" => Result::Ok(true),
-            "false" => Result::Ok(false),
-            _ => Result::Err(()),
-        };
+        let input = input.to_lowercase();
+        for (literal, value) in self.literals.iter() {
+            if *literal == input {
+                return Result::Ok(*value);
+            }
+        }
+
+        return Result::Err(());
     }
 
     fn can_start_with(&self, input: String) -> bool {
-        return "true".starts_with(&input) || "false".starts_with(&input);
+        let input = input.to_lowercase();
+
+        return self.literals.iter().any(|(literal, _)| literal.starts_with(&input));
     }
 }
 
+/// Boolean calculator with default literals
 pub fn boolean_calculator() -> ExpressionDeclarator<bool> {
-    let mut calculator = ExpressionDeclarator::<bool>::new(Box::new(BoolHandler {}));
+    return boolean_calculator_with(BoolHandler::new());
+}
+
+/// Boolean calculator with custom literals, e.g. `BoolHandler::new().with_literals("yes", "no")`.
+/// Operations from the highest priority to the lowest:
+///
+/// | Operation   | Signatures         |
+/// |-------------|--------------------|
+/// | NOT         | `!`, `not`         |
+/// | AND, NAND   | `&`, `and`, `nand` |
+/// | XOR         | `^`, `xor`         |
+/// | OR, NOR     | `\|`, `or`, `nor`  |
+/// | Implication | `->`               |
+/// | Equivalence | `<->`              |
+///
+/// Implication is right associative, `&` and `|` are short-circuit.
+pub fn boolean_calculator_with(handler: BoolHandler) -> ExpressionDeclarator<bool> {
+    let mut calculator = ExpressionDeclarator::<bool>::new(Box::new(handler));
+    calculator.add_line_comment("#".to_string());
+
+    for signature in ["|", "or"].iter() {
+        calculator.add_lazy(
+            signature.to_string(),
+            "OR".to_string(),
+            OperationType::Infix,
+            Box::new(|ops: &[Thunk<bool>]| { Result::Ok(ops[0]()? || ops[1]()?) }),
+            2,
+            OR_ORDER,
+        );
+    }
 
     calculator.add_infix(
-        "|".to_string(),
-        "OR".to_string(),
-        Box::new(|op1, op2| { op1 | op2 }),
-        LOW_ORDER,
+        "nor".to_string(),
+        "NOR".to_string(),
+        Box::new(|op1, op2| { !(op1 | op2) }),
+        OR_ORDER,
     );
 
+    for signature in ["&", "and"].iter() {
+        calculator.add_lazy(
+            signature.to_string(),
+            "AND".to_string(),
+            OperationType::Infix,
+            Box::new(|ops: &[Thunk<bool>]| { Result::Ok(ops[0]()? && ops[1]()?) }),
+            2,
+            AND_ORDER,
+        );
+    }
+
     calculator.add_infix(
-        "&".to_string(),
-        "AND".to_string(),
-        Box::new(|op1, op2| { op1 & op2 }),
-        LOW_ORDER,
+        "nand".to_string(),
+        "NAND".to_string(),
+        Box::new(|op1, op2| { !(op1 & op2) }),
+        AND_ORDER,
     );
 
-    calculator.add_infix(
-        "^".to_string(),
-        "XOR".to_string(),
-        Box::new(|op1, op2| { op1 ^ op2 }),
-        LOW_ORDER,
+    for signature in ["^", "xor"].iter() {
+        calculator.add_infix(
+            signature.to_string(),
+            "XOR".to_string(),
+            Box::new(|op1, op2| { op1 ^ op2 }),
+            XOR_ORDER,
+        );
+    }
+
+    calculator.add_right_infix(
+        "->".to_string(),
+        "Implication".to_string(),
+        Box::new(|op1, op2| { !op1 | op2 }),
+        IMPLICATION_ORDER,
     );
 
-    calculator.add_prefix(
-        "!".to_string(),
-        "NOT".to_string(),
-        Box::new(|op1| { !op1 }),
-        HIGH_ORDER,
+    calculator.add_infix(
+        "<->".to_string(),
+        "Equivalence".to_string(),
+        Box::new(|op1, op2| { op1 == op2 }),
+        EQUIVALENCE_ORDER,
     );
 
+    for signature in ["!", "not"].iter() {
+        calculator.add_prefix(
+            signature.to_string(),
+            "NOT".to_string(),
+            Box::new(|op1| { !op1 }),
+            NOT_ORDER,
+        );
+    }
+
     return calculator;
-}
\ No newline at end of file
+}
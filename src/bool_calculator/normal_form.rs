@@ -0,0 +1,254 @@
+use crate::ast::AstNode;
+use crate::bool_calculator::truth_table::{TruthTable, TruthTableError};
+use crate::declarator::ExpressionDeclarator;
+use crate::operation::{Operation, OperationType};
+use crate::token::{Span, Token};
+use std::sync::Arc;
+
+pub enum NormalFormError {
+    Table(TruthTableError),
+    /// Calculator has no operation, required for normal form, e.g. "&"
+    MissingOperation(String),
+    /// Normal form nests lazy operations deeper than the maximum depth of the calculator
+    TooDeep { depth: usize, max: usize },
+}
+
+/// Implicant over variables of truth table.
+/// Bits of `mask` are variables present in the implicant, bits of `value` are their values.
+/// The first variable is the most significant bit.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+struct Implicant {
+    value: usize,
+    mask: usize,
+}
+
+impl Implicant {
+    fn covers(&self, minterm: usize) -> bool {
+        return minterm & self.mask == self.value;
+    }
+
+    fn literals(&self) -> u32 {
+        return self.mask.count_ones();
+    }
+}
+
+/// Canonical disjunctive normal form, OR of all minterms
+pub fn to_dnf(calculator: &ExpressionDeclarator<bool>, ast: &AstNode<bool>, max_variables: usize) -> Result<AstNode<bool>, NormalFormError> {
+    let table = TruthTable::from_ast(ast, max_variables).map_err(NormalFormError::Table)?;
+    let full = (1 << table.variables.len()) - 1;
+    let implicants: Vec<Implicant> = minterms(&table, true).into_iter()
+        .map(|value| Implicant { value, mask: full })
+        .collect();
+
+    return checked(calculator, Builder::new(calculator, &table.variables)?.join(&implicants, true));
+}
+
+/// Canonical conjunctive normal form, AND of all maxterms
+pub fn to_cnf(calculator: &ExpressionDeclarator<bool>, ast: &AstNode<bool>, max_variables: usize) -> Result<AstNode<bool>, NormalFormError> {
+    let table = TruthTable::from_ast(ast, max_variables).map_err(NormalFormError::Table)?;
+    let full = (1 << table.variables.len()) - 1;
+    let implicants: Vec<Implicant> = minterms(&table, false).into_iter()
+        .map(|value| Implicant { value, mask: full })
+        .collect();
+
+    return checked(calculator, Builder::new(calculator, &table.variables)?.join(&implicants, false));
+}
+
+/// Minimal sum of products by Quine–McCluskey method
+pub fn minimize(calculator: &ExpressionDeclarator<bool>, ast: &AstNode<bool>, max_variables: usize) -> Result<AstNode<bool>, NormalFormError> {
+    let table = TruthTable::from_ast(ast, max_variables).map_err(NormalFormError::Table)?;
+    let ones = minterms(&table, true);
+    let primes = prime_implicants(&ones, table.variables.len());
+    let cover = minimal_cover(&ones, &primes);
+
+    return checked(calculator, Builder::new(calculator, &table.variables)?.join(&cover, true));
+}
+
+fn checked(calculator: &ExpressionDeclarator<bool>, ast: AstNode<bool>) -> Result<AstNode<bool>, NormalFormError> {
+    let depth = ast.lazy_depth();
+    if depth > calculator.max_depth {
+        return Result::Err(NormalFormError::TooDeep { depth, max: calculator.max_depth });
+    }
+
+    return Result::Ok(ast);
+}
+
+fn minterms(table: &TruthTable, result: bool) -> Vec<usize> {
+    return table.rows.iter()
+        .enumerate()
+        .filter(|(_, row)| row.result == result)
+        .map(|(index, _)| index)
+        .collect();
+}
+
+fn prime_implicants(minterms: &[usize], variables: usize) -> Vec<Implicant> {
+    let full = (1 << variables) - 1;
+    let mut current: Vec<Implicant> = minterms.iter().map(|value| Implicant { value: *value, mask: full }).collect();
+    let mut primes: Vec<Implicant> = Vec::new();
+
+    while !current.is_empty() {
+        let mut combined = vec![false; current.len()];
+        let mut next: Vec<Implicant> = Vec::new();
+        for i in 0..current.len() {
+            for j in (i + 1)..current.len() {
+                let (a, b) = (current[i], current[j]);
+                let difference = a.value ^ b.value;
+                if a.mask != b.mask || difference.count_ones() != 1 {
+                    continue;
+                }
+                combined[i] = true;
+                combined[j] = true;
+                let merged = Implicant { value: a.value & !difference, mask: a.mask & !difference };
+                if !next.contains(&merged) {
+                    next.push(merged);
+                }
+            }
+        }
+        for (implicant, used) in current.iter().zip(combined.iter()) {
+            if !used && !primes.contains(implicant) {
+                primes.push(*implicant);
+            }
+        }
+        current = next;
+    }
+
+    return primes;
+}
+
+/// Cover minterms with the least number of implicants, and then with the least number of literals.
+/// Essential implicants are taken first, the rest is found by branch and bound search.
+fn minimal_cover(minterms: &[usize], primes: &[Implicant]) -> Vec<Implicant> {
+    let mut chosen: Vec<Implicant> = Vec::new();
+    for minterm in minterms {
+        let covering: Vec<&Implicant> = primes.iter().filter(|it| it.covers(*minterm)).collect();
+        if covering.len() == 1 && !chosen.contains(covering[0]) {
+            chosen.push(*covering[0]);
+        }
+    }
+
+    let rest: Vec<usize> = minterms.iter()
+        .filter(|minterm| !chosen.iter().any(|it| it.covers(**minterm)))
+        .cloned()
+        .collect();
+    let candidates: Vec<Implicant> = primes.iter().filter(|it| !chosen.contains(it)).cloned().collect();
+
+    let mut best: Option<Vec<Implicant>> = None;
+    search_cover(&rest, &candidates, &mut Vec::new(), &mut best);
+    chosen.extend(best.unwrap_or_default());
+    chosen.sort_by_key(|it| (std::cmp::Reverse(it.mask), std::cmp::Reverse(it.value)));
+
+    return chosen;
+}
+
+fn search_cover(rest: &[usize], candidates: &[Implicant], current: &mut Vec<Implicant>, best: &mut Option<Vec<Implicant>>) {
+    let cost = |cover: &Vec<Implicant>| (cover.len(), cover.iter().map(|it| it.literals()).sum::<u32>());
+    if let Some(found) = best {
+        if cost(current) >= cost(found) {
+            return;
+        }
+    }
+
+    let uncovered = rest.iter().find(|minterm| !current.iter().any(|it| it.covers(**minterm)));
+    let minterm = match uncovered {
+        Some(minterm) => *minterm,
+        None => {
+            *best = Some(current.clone());
+            return;
+        }
+    };
+
+    for candidate in candidates.iter().filter(|it| it.covers(minterm)) {
+        current.push(*candidate);
+        search_cover(rest, candidates, current, best);
+        current.pop();
+    }
+}
+
+/// Creates AST nodes with operations of calculator
+struct Builder<'a> {
+    variables: &'a [String],
+    and: Arc<Operation<bool>>,
+    or: Arc<Operation<bool>>,
+    not: Arc<Operation<bool>>,
+}
+
+impl<'a> Builder<'a> {
+    fn new(calculator: &ExpressionDeclarator<bool>, variables: &'a [String]) -> Result<Builder<'a>, NormalFormError> {
+        return Result::Ok(Builder {
+            variables,
+            and: find(calculator, "&", OperationType::Infix)?,
+            or: find(calculator, "|", OperationType::Infix)?,
+            not: find(calculator, "!", OperationType::Prefix)?,
+        });
+    }
+
+    /// Sum of products of literals when `sum` is set, product of sums of negated literals otherwise
+    fn join(&self, implicants: &[Implicant], sum: bool) -> AstNode<bool> {
+        let (outer, inner) = if sum { (&self.or, &self.and) } else { (&self.and, &self.or) };
+        let terms: Vec<AstNode<bool>> = implicants.iter()
+            .map(|implicant| {
+                let literals: Vec<AstNode<bool>> = (0..self.variables.len())
+                    .filter(|i| implicant.mask & self.bit(*i) != 0)
+                    .map(|i| self.literal(i, (implicant.value & self.bit(i) != 0) == sum))
+                    .collect();
+                self.chain(literals, inner, sum)
+            })
+            .collect();
+
+        return self.chain(terms, outer, !sum);
+    }
+
+    /// Balanced tree of operations over nodes, so its depth grows as log2 of their number
+    fn chain(&self, mut nodes: Vec<AstNode<bool>>, op: &Arc<Operation<bool>>, empty: bool) -> AstNode<bool> {
+        if nodes.is_empty() {
+            return constant(empty);
+        }
+
+        while nodes.len() > 1 {
+            let mut pairs = Vec::with_capacity(nodes.len().div_ceil(2));
+            let mut iter = nodes.into_iter();
+            while let Some(left) = iter.next() {
+                pairs.push(match iter.next() {
+                    Some(right) => AstNode::Binary {
+                        op: op.clone(),
+                        p1: Box::new(left),
+                        p2: Box::new(right),
+                        token: Token::Operation { span: Span::default(), val: op.clone() },
+                    },
+                    None => left,
+                });
+            }
+            nodes = pairs;
+        }
+
+        return nodes.pop().unwrap();
+    }
+
+    fn literal(&self, index: usize, positive: bool) -> AstNode<bool> {
+        let name = self.variables[index].clone();
+        let variable = AstNode::Variable { name: name.clone(), token: Token::Variable { span: Span::default(), val: name } };
+        if positive {
+            return variable;
+        }
+
+        return AstNode::Unary {
+            op: self.not.clone(),
+            p1: Box::new(variable),
+            token: Token::Operation { span: Span::default(), val: self.not.clone() },
+        };
+    }
+
+    fn bit(&self, index: usize) -> usize {
+        return 1 << (self.variables.len() - 1 - index);
+    }
+}
+
+fn find(calculator: &ExpressionDeclarator<bool>, signature: &str, op_type: OperationType) -> Result<Arc<Operation<bool>>, NormalFormError> {
+    return calculator.signatures.operation(signature, &op_type)
+        .map(|index| calculator.operations[index].clone())
+        .ok_or_else(|| NormalFormError::MissingOperation(signature.to_string()));
+}
+
+fn constant(value: bool) -> AstNode<bool> {
+    return AstNode::Primitive { val: value, token: Token::Primitive { span: Span::default(), val: value, original: value.to_string() } };
+}
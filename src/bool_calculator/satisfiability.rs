@@ -0,0 +1,98 @@
+use crate::ast::AstNode;
+use crate::bool_calculator::truth_table::{assignments, TruthTableError};
+use crate::declarator::ExpressionDeclarator;
+use std::collections::HashMap;
+
+/// Values of variables
+#[derive(Clone, PartialEq, Debug)]
+pub struct Assignment {
+    pub variables: Vec<String>,
+    pub values: Vec<bool>,
+}
+
+impl Assignment {
+    pub fn get(&self, variable: &str) -> Option<bool> {
+        return self.variables.iter().position(|it| it == variable).map(|index| self.values[index]);
+    }
+
+    pub fn to_map(&self) -> HashMap<String, bool> {
+        return self.variables.iter().cloned().zip(self.values.iter().cloned()).collect();
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Satisfiability {
+    /// Expression is true for the witness assignment
+    Satisfiable(Assignment),
+    Unsatisfiable,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Validity {
+    Valid,
+    /// Assignment, for which the statement is false
+    Counterexample(Assignment),
+}
+
+/// Check if expression is true for some assignment
+pub fn satisfiable(calculator: &ExpressionDeclarator<bool>, input: &str, max_variables: usize) -> Result<Satisfiability, TruthTableError> {
+    let ast = calculator.build_ast(input).map_err(TruthTableError::Expression)?;
+    let found = search(&[&ast], max_variables, |values| values[0])?;
+
+    return Result::Ok(match found {
+        Some(witness) => Satisfiability::Satisfiable(witness),
+        None => Satisfiability::Unsatisfiable,
+    });
+}
+
+/// Check if expressions have the same value for every assignment
+pub fn equivalent(calculator: &ExpressionDeclarator<bool>, left: &str, right: &str, max_variables: usize) -> Result<Validity, TruthTableError> {
+    return validity(calculator, left, right, max_variables, |values| values[0] == values[1]);
+}
+
+/// Check if conclusion is true for every assignment, for which premise is true
+pub fn implies(calculator: &ExpressionDeclarator<bool>, premise: &str, conclusion: &str, max_variables: usize) -> Result<Validity, TruthTableError> {
+    return validity(calculator, premise, conclusion, max_variables, |values| !values[0] || values[1]);
+}
+
+fn validity(
+    calculator: &ExpressionDeclarator<bool>,
+    left: &str,
+    right: &str,
+    max_variables: usize,
+    holds: fn(&[bool]) -> bool,
+) -> Result<Validity, TruthTableError> {
+    let left = calculator.build_ast(left).map_err(TruthTableError::Expression)?;
+    let right = calculator.build_ast(right).map_err(TruthTableError::Expression)?;
+    let found = search(&[&left, &right], max_variables, |values| !holds(values))?;
+
+    return Result::Ok(match found {
+        Some(counterexample) => Validity::Counterexample(counterexample),
+        None => Validity::Valid,
+    });
+}
+
+/// Find the first assignment of variables of all expressions, for which values of expressions match the predicate.
+/// Assignments are checked in the truth table order, so the cost grows as 2^variables.
+fn search(asts: &[&AstNode<bool>], max_variables: usize, matches: impl Fn(&[bool]) -> bool) -> Result<Option<Assignment>, TruthTableError> {
+    let mut variables: Vec<String> = Vec::new();
+    for ast in asts {
+        for name in ast.variables() {
+            if !variables.contains(&name) {
+                variables.push(name);
+            }
+        }
+    }
+
+    for (values, scope) in assignments(&variables, max_variables)? {
+        let mut results = Vec::with_capacity(asts.len());
+        for ast in asts {
+            results.push(ast.calculate_with(&scope).map_err(TruthTableError::Expression)?);
+        }
+        if matches(&results) {
+            return Result::Ok(Some(Assignment { variables: variables.clone(), values }));
+        }
+    }
+
+    return Result::Ok(None);
+}
@@ -0,0 +1,108 @@
+use crate::ast::AstNode;
+use crate::declarator::ExpressionDeclarator;
+use crate::token::Token;
+use std::collections::HashMap;
+
+/// Values of all variables for every assignment.
+/// Rows go from all `false` to all `true`, the first variable changes the slowest.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TruthTable {
+    pub variables: Vec<String>,
+    pub rows: Vec<TruthTableRow>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct TruthTableRow {
+    /// Values of variables in order of `TruthTable::variables`
+    pub values: Vec<bool>,
+    pub result: bool,
+}
+
+pub enum TruthTableError {
+    /// Expression can't be parsed or calculated
+    Expression(Token<bool>),
+    TooManyVariables { count: usize, max: usize },
+}
+
+/// Build truth table for expression.
+/// Variables are taken from the expression, so the calculator must declare them or allow free variables.
+pub fn truth_table(calculator: &ExpressionDeclarator<bool>, input: &str, max_variables: usize) -> Result<TruthTable, TruthTableError> {
+    let ast = calculator.build_ast(input).map_err(TruthTableError::Expression)?;
+
+    return TruthTable::from_ast(&ast, max_variables);
+}
+
+impl TruthTable {
+    pub fn from_ast(ast: &AstNode<bool>, max_variables: usize) -> Result<TruthTable, TruthTableError> {
+        let variables = ast.variables();
+        let mut rows = Vec::new();
+        for (values, scope) in assignments(&variables, max_variables)? {
+            let result = ast.calculate_with(&scope).map_err(TruthTableError::Expression)?;
+            rows.push(TruthTableRow { values, result });
+        }
+
+        return Result::Ok(TruthTable { variables, rows });
+    }
+
+    /// Render table as aligned text columns
+    pub fn to_text(&self) -> String {
+        let mut header: Vec<String> = self.variables.clone();
+        header.push("result".to_string());
+        let widths: Vec<usize> = header.iter().map(|it| it.chars().count().max("false".len())).collect();
+
+        let mut lines = vec![format_line(&header, &widths)];
+        lines.push(widths.iter().map(|width| "-".repeat(*width)).collect::<Vec<String>>().join("-+-"));
+        for row in self.rows.iter() {
+            lines.push(format_line(&row.cells(), &widths));
+        }
+
+        return lines.join("\n");
+    }
+
+    /// Render table as CSV with header
+    pub fn to_csv(&self) -> String {
+        let mut header: Vec<String> = self.variables.clone();
+        header.push("result".to_string());
+
+        let mut lines = vec![header.join(",")];
+        for row in self.rows.iter() {
+            lines.push(row.cells().join(","));
+        }
+
+        return lines.join("\n");
+    }
+}
+
+impl TruthTableRow {
+    fn cells(&self) -> Vec<String> {
+        let mut cells: Vec<String> = self.values.iter().map(|it| it.to_string()).collect();
+        cells.push(self.result.to_string());
+
+        return cells;
+    }
+}
+
+fn format_line(cells: &[String], widths: &[usize]) -> String {
+    return cells.iter()
+        .zip(widths.iter())
+        .map(|(cell, width)| format!("{:width$}", cell, width = width))
+        .collect::<Vec<String>>()
+        .join(" | ")
+        .trim_end()
+        .to_string();
+}
+
+/// Values of variables and the scope to calculate with them for every assignment in the truth table order
+pub(crate) fn assignments(variables: &[String], max_variables: usize)
+    -> Result<impl Iterator<Item = (Vec<bool>, HashMap<String, bool>)> + '_, TruthTableError> {
+    let count = variables.len();
+    if count > max_variables || count >= usize::BITS as usize {
+        return Result::Err(TruthTableError::TooManyVariables { count, max: max_variables });
+    }
+
+    return Result::Ok((0..(1usize << count)).map(move |assignment| {
+        let values: Vec<bool> = (0..count).map(|i| assignment & (1 << (count - 1 - i)) != 0).collect();
+        let scope = variables.iter().cloned().zip(values.iter().copied()).collect();
+        (values, scope)
+    }));
+}
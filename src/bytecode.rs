@@ -0,0 +1,378 @@
+use crate::ast::AstNode;
+use crate::calculator::{BoxedThunk, Executor, Thunk};
+use crate::declarator::ExpressionDeclarator;
+use crate::flat_ast::OperationId;
+use crate::token::{Span, Token};
+use std::collections::HashMap;
+
+/// Instruction of compiled expression
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Instruction {
+    /// Push constant by index in the constant pool
+    Push(u32),
+    /// Push value of variable by index in variables of program
+    Load(u32),
+    /// Replace the top `arity` values with the result of eager operation
+    Call { op: OperationId, arity: u8 },
+    /// Push the result of lazy operation. Its operands are code blocks `first_block..first_block + arity`,
+    /// which are calculated on demand.
+    CallLazy { op: OperationId, arity: u8, first_block: u32 },
+    /// Continue from instruction, used to pass code blocks of lazy operands
+    Jump(u32),
+}
+
+/// Expression compiled to linear code of stack machine.
+/// Operations are referred by index, so program runs with the declarator, which compiled it.
+#[derive(Clone)]
+pub struct Program<T: Clone> {
+    code: Vec<Instruction>,
+    /// Values and original texts of constants
+    constants: Vec<(T, String)>,
+    /// Names of variables with the span of the first appearance
+    variables: Vec<(String, Span)>,
+    /// Ranges of code of lazy operands
+    blocks: Vec<(u32, u32)>,
+}
+
+/// Node, index of the next operand and, for lazy operations, position of the jump over operands and the first block
+type Frame<'a, T> = (&'a AstNode<T>, usize, Option<(usize, usize)>);
+
+impl<T: 'static + Clone> ExpressionDeclarator<T> {
+    /// Compile AST to program. Operation, which isn't declared in the declarator, is reported as an error.
+    pub fn compile(&self, ast: &AstNode<T>) -> Result<Program<T>, Token<T>> {
+        let mut program = Program { code: Vec::new(), constants: Vec::new(), variables: Vec::new(), blocks: Vec::new() };
+        let mut frames: Vec<Frame<T>> = vec![(ast, 0, None)];
+
+        while let Some((node, next, lazy)) = frames.pop() {
+            let (op, token, operands): (_, _, Vec<&AstNode<T>>) = match node {
+                AstNode::Primitive { val, token } => {
+                    program.constants.push((val.clone(), token.get_value()));
+                    program.code.push(Instruction::Push((program.constants.len() - 1) as u32));
+                    continue;
+                }
+                AstNode::Variable { name, token } => {
+                    let index = match program.variables.iter().position(|(it, _)| it == name) {
+                        Some(index) => index,
+                        None => {
+                            program.variables.push((name.clone(), token.get_span()));
+                            program.variables.len() - 1
+                        }
+                    };
+                    program.code.push(Instruction::Load(index as u32));
+                    continue;
+                }
+                AstNode::Unary { op, p1, token } => (op, token, vec![p1]),
+                AstNode::Binary { op, p1, p2, token } => (op, token, vec![p1, p2]),
+                AstNode::Nary { op, args, token } => (op, token, args.iter().collect()),
+            };
+            let id = self.operation_id(op).ok_or_else(|| token.clone())?;
+            let arity = operands.len() as u8;
+
+            let lazy = match (&op.executor, lazy) {
+                (Executor::Lazy(_), None) => {
+                    program.code.push(Instruction::Jump(0));
+                    program.blocks.extend(operands.iter().map(|_| (0, 0)));
+                    Some((program.code.len() - 1, program.blocks.len() - operands.len()))
+                }
+                _ => lazy,
+            };
+            if let Some((_, first_block)) = lazy {
+                let position = program.code.len() as u32;
+                if next > 0 {
+                    program.blocks[first_block + next - 1].1 = position;
+                }
+                if next < operands.len() {
+                    program.blocks[first_block + next].0 = position;
+                }
+            }
+            if next < operands.len() {
+                frames.push((node, next + 1, lazy));
+                frames.push((operands[next], 0, None));
+                continue;
+            }
+
+            match lazy {
+                Some((jump, first_block)) => {
+                    program.code[jump] = Instruction::Jump(program.code.len() as u32);
+                    program.code.push(Instruction::CallLazy { op: id, arity, first_block: first_block as u32 });
+                }
+                None => program.code.push(Instruction::Call { op: id, arity }),
+            }
+        }
+
+        return Result::Ok(program);
+    }
+}
+
+impl<T: 'static + Clone> Program<T> {
+    pub fn code(&self) -> &[Instruction] {
+        return &self.code;
+    }
+
+    /// Names of variables in order of the first appearance. Values are passed to `Vm::run` in this order.
+    pub fn variables(&self) -> Vec<String> {
+        return self.variables.iter().map(|(name, _)| name.clone()).collect();
+    }
+
+    /// Calculate program without variables with the declarator, which compiled it
+    pub fn calculate(&self, declarator: &ExpressionDeclarator<T>) -> Result<T, Token<T>> {
+        return self.calculate_with(declarator, &HashMap::new());
+    }
+
+    /// Calculate program with values of variables. Unknown variable is reported as an error.
+    pub fn calculate_with(&self, declarator: &ExpressionDeclarator<T>, variables: &HashMap<String, T>) -> Result<T, Token<T>> {
+        let mut values = Vec::with_capacity(self.variables.len());
+        for (name, span) in self.variables.iter() {
+            match variables.get(name) {
+                Some(val) => values.push(val.clone()),
+                None => return Result::Err(Token::Variable { span: *span, val: name.clone() }),
+            }
+        }
+
+        return Vm::new().run(self, declarator, &values);
+    }
+
+    /// Calculate program for every row of output with columns of variables.
+    /// Unknown variable and variable with a column shorter than output are reported as errors.
+    pub fn calculate_columns(&self, declarator: &ExpressionDeclarator<T>, columns: &HashMap<String, &[T]>, out: &mut [T]) -> Result<(), Token<T>> {
+        let mut ordered: Vec<&[T]> = Vec::with_capacity(self.variables.len());
+        for (name, span) in self.variables.iter() {
+            match columns.get(name) {
+                Some(column) => ordered.push(column),
+                None => return Result::Err(Token::Variable { span: *span, val: name.clone() }),
+            }
+        }
+
+        return BatchVm::new().run(self, declarator, &ordered, out);
+    }
+
+    /// Listing of the program, one instruction per line with operands explained after ';'
+    pub fn disassemble(&self, declarator: &ExpressionDeclarator<T>) -> String {
+        let width = self.code.len().saturating_sub(1).to_string().len();
+        let mut lines: Vec<String> = Vec::with_capacity(self.code.len());
+        for (address, instruction) in self.code.iter().enumerate() {
+            let text = match instruction {
+                Instruction::Push(index) => format!("push {:<12}; {}", index, self.constants[*index as usize].1),
+                Instruction::Load(index) => format!("load {:<12}; {}", index, self.variables[*index as usize].0),
+                Instruction::Call { op, arity } => {
+                    format!("call {:<12}; {}", format!("{}/{}", op, arity), declarator.operations[*op as usize].pretty_print())
+                }
+                Instruction::CallLazy { op, arity, first_block } => {
+                    let blocks: Vec<String> = self.blocks[*first_block as usize..*first_block as usize + *arity as usize].iter()
+                        .map(|(start, end)| format!("{:0width$}..{:0width$}", start, end, width = width))
+                        .collect();
+                    format!(
+                        "lazy {:<12}; {} with {}",
+                        format!("{}/{}", op, arity),
+                        declarator.operations[*op as usize].pretty_print(),
+                        blocks.join(", "),
+                    )
+                }
+                Instruction::Jump(target) => format!("jump {:0width$}", target, width = width),
+            };
+            lines.push(format!("{:0width$}  {}", address, text.trim_end(), width = width));
+        }
+
+        return lines.join("\n");
+    }
+}
+
+/// Stack machine, which runs programs. Value stack is kept between runs, so it's allocated once,
+/// and eager operations get their operands as a slice of it.
+pub struct Vm<T: Clone> {
+    stack: Vec<T>,
+}
+
+impl<T: 'static + Clone> Vm<T> {
+    pub fn new() -> Vm<T> {
+        return Vm { stack: Vec::new() };
+    }
+
+    /// Run program with values of its variables in order of `Program::variables`.
+    /// The first variable without value is reported as an error.
+    pub fn run(&mut self, program: &Program<T>, declarator: &ExpressionDeclarator<T>, variables: &[T]) -> Result<T, Token<T>> {
+        if let Some((name, span)) = program.variables.get(variables.len()) {
+            return Result::Err(Token::Variable { span: *span, val: name.clone() });
+        }
+        self.stack.clear();
+        execute(program, declarator, variables, &mut self.stack, 0, program.code.len())?;
+
+        return Result::Ok(self.stack.pop().unwrap());
+    }
+}
+
+impl<T: 'static + Clone> Default for Vm<T> {
+    fn default() -> Self {
+        return Vm::new();
+    }
+}
+
+/// Run code from start to end. Lazy operands are calculated on their own stacks.
+fn execute<T: 'static + Clone>(
+    program: &Program<T>,
+    declarator: &ExpressionDeclarator<T>,
+    variables: &[T],
+    stack: &mut Vec<T>,
+    start: usize,
+    end: usize,
+) -> Result<(), Token<T>> {
+    let mut address = start;
+    while address < end {
+        match program.code[address] {
+            Instruction::Push(index) => stack.push(program.constants[index as usize].0.clone()),
+            Instruction::Load(index) => stack.push(variables[index as usize].clone()),
+            Instruction::Call { op, arity } => match &declarator.operations[op as usize].executor {
+                Executor::Eager(executor) => {
+                    let first = stack.len() - arity as usize;
+                    let value = executor(&stack[first..]);
+                    stack.truncate(first);
+                    stack.push(value);
+                }
+                Executor::Lazy(_) => return Result::Err(Token::Operation { span: Span::default(), val: declarator.operations[op as usize].clone() }),
+            },
+            Instruction::CallLazy { op, arity, first_block } => {
+                stack.push(call_lazy(program, declarator, variables, op, arity, first_block)?);
+            }
+            Instruction::Jump(target) => {
+                address = target as usize;
+                continue;
+            }
+        }
+        address += 1;
+    }
+
+    return Result::Ok(());
+}
+
+/// Call lazy operation with thunks, which run code blocks of its operands
+fn call_lazy<T: 'static + Clone>(
+    program: &Program<T>,
+    declarator: &ExpressionDeclarator<T>,
+    variables: &[T],
+    op: OperationId,
+    arity: u8,
+    first_block: u32,
+) -> Result<T, Token<T>> {
+    let executor = match &declarator.operations[op as usize].executor {
+        Executor::Lazy(executor) => executor,
+        Executor::Eager(_) => return Result::Err(Token::Operation { span: Span::default(), val: declarator.operations[op as usize].clone() }),
+    };
+
+    let blocks = &program.blocks[first_block as usize..first_block as usize + arity as usize];
+    let thunks: Vec<BoxedThunk<T>> = blocks.iter()
+        .map(|(start, end)| Box::new(move || {
+            let mut stack = Vec::new();
+            execute(program, declarator, variables, &mut stack, *start as usize, *end as usize)?;
+            Result::Ok(stack.pop().unwrap())
+        }) as BoxedThunk<T>)
+        .collect();
+    let refs: Vec<Thunk<T>> = thunks.iter().map(|it| it.as_ref()).collect();
+
+    return executor(&refs);
+}
+
+/// Rows, which are calculated together by `BatchVm`
+pub const BATCH_SIZE: usize = 1024;
+
+/// Stack machine over columns of values. Rows are calculated in chunks of `BATCH_SIZE`,
+/// operations with vectorized executor are called once per chunk, others once per row.
+/// Column buffers are kept between chunks and runs.
+pub struct BatchVm<T: Clone> {
+    stack: Vec<Vec<T>>,
+    /// Free column buffers
+    pool: Vec<Vec<T>>,
+    /// Values of variables in one row for lazy operations
+    row: Vec<T>,
+    /// Operands in one row for eager executors
+    operands: Vec<T>,
+}
+
+impl<T: 'static + Clone> BatchVm<T> {
+    pub fn new() -> BatchVm<T> {
+        return BatchVm { stack: Vec::new(), pool: Vec::new(), row: Vec::new(), operands: Vec::new() };
+    }
+
+    /// Calculate program for every row of output with columns of its variables in order of `Program::variables`.
+    /// The first variable without column or with a column shorter than output is reported as an error.
+    pub fn run(&mut self, program: &Program<T>, declarator: &ExpressionDeclarator<T>, columns: &[&[T]], out: &mut [T]) -> Result<(), Token<T>> {
+        for (index, (name, span)) in program.variables.iter().enumerate() {
+            let short = match columns.get(index) {
+                Some(column) => column.len() < out.len(),
+                None => true,
+            };
+            if short {
+                return Result::Err(Token::Variable { span: *span, val: name.clone() });
+            }
+        }
+        self.pool.append(&mut self.stack);
+
+        let mut start = 0;
+        while start < out.len() {
+            let end = (start + BATCH_SIZE).min(out.len());
+            self.run_chunk(program, declarator, columns, start, end)?;
+            let result = self.stack.pop().unwrap();
+            out[start..end].clone_from_slice(&result);
+            self.pool.push(result);
+            start = end;
+        }
+
+        return Result::Ok(());
+    }
+
+    fn run_chunk(&mut self, program: &Program<T>, declarator: &ExpressionDeclarator<T>, columns: &[&[T]], start: usize, end: usize) -> Result<(), Token<T>> {
+        let rows = end - start;
+        let mut address = 0;
+        while address < program.code.len() {
+            let mut column = self.pool.pop().unwrap_or_default();
+            column.clear();
+            match program.code[address] {
+                Instruction::Push(index) => column.resize(rows, program.constants[index as usize].0.clone()),
+                Instruction::Load(index) => column.extend_from_slice(&columns[index as usize][start..end]),
+                Instruction::Call { op, arity } => {
+                    let operation = &declarator.operations[op as usize];
+                    let first = self.stack.len() - arity as usize;
+                    let operands: Vec<&[T]> = self.stack[first..].iter().map(|it| it.as_slice()).collect();
+                    match (&operation.vectorized, &operation.executor) {
+                        (Some(vectorized), _) => {
+                            column.extend_from_slice(operands[0]);
+                            vectorized(&operands, &mut column);
+                        }
+                        (None, Executor::Eager(executor)) => {
+                            for row in 0..rows {
+                                self.operands.clear();
+                                self.operands.extend(operands.iter().map(|it| it[row].clone()));
+                                column.push(executor(&self.operands));
+                            }
+                        }
+                        (None, Executor::Lazy(_)) => {
+                            return Result::Err(Token::Operation { span: Span::default(), val: operation.clone() });
+                        }
+                    }
+                    self.pool.extend(self.stack.drain(first..));
+                }
+                Instruction::CallLazy { op, arity, first_block } => {
+                    for row in start..end {
+                        self.row.clear();
+                        self.row.extend(columns.iter().map(|it| it[row].clone()));
+                        column.push(call_lazy(program, declarator, &self.row, op, arity, first_block)?);
+                    }
+                }
+                Instruction::Jump(target) => {
+                    self.pool.push(column);
+                    address = target as usize;
+                    continue;
+                }
+            }
+            self.stack.push(column);
+            address += 1;
+        }
+
+        return Result::Ok(());
+    }
+}
+
+impl<T: 'static + Clone> Default for BatchVm<T> {
+    fn default() -> Self {
+        return BatchVm::new();
+    }
+}
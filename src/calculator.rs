@@ -1,8 +1,12 @@
-pub trait OperationExecutor<T>: Fn(Vec<T>) -> T {
+use crate::token::Token;
+
+/// Executors are `Send + Sync`, so a declarator with its operations can be shared between threads.
+/// Operands are borrowed from the stack of calculation, so a call doesn't allocate them.
+pub trait OperationExecutor<T>: Fn(&[T]) -> T + Send + Sync {
     fn clone_box<'a>(&self) -> Box<dyn 'a + OperationExecutor<T>> where Self: 'a;
 }
 
-impl<T, F: Fn(Vec<T>) -> T + Clone> OperationExecutor<T> for F {
+impl<T, F: Fn(&[T]) -> T + Clone + Send + Sync> OperationExecutor<T> for F {
     fn clone_box<'a>(&self) -> Box<dyn 'a + OperationExecutor<T>> where Self: 'a, {
         Box::new(self.clone())
     }
@@ -15,11 +19,11 @@ impl<'a, T: 'a> Clone for Box<dyn 'a + OperationExecutor<T>> {
 }
 
 
-pub trait BinaryOperationExecutor<T>: Fn(T, T) -> T {
+pub trait BinaryOperationExecutor<T>: Fn(T, T) -> T + Send + Sync {
     fn clone_box<'a>(&self) -> Box<dyn 'a + BinaryOperationExecutor<T>> where Self: 'a;
 }
 
-impl<T, F: Fn(T, T) -> T + Clone> BinaryOperationExecutor<T> for F {
+impl<T, F: Fn(T, T) -> T + Clone + Send + Sync> BinaryOperationExecutor<T> for F {
     fn clone_box<'a>(&self) -> Box<dyn 'a + BinaryOperationExecutor<T>> where Self: 'a, {
         Box::new(self.clone())
     }
@@ -32,11 +36,11 @@ impl<'a, T: 'a> Clone for Box<dyn 'a + BinaryOperationExecutor<T>> {
 }
 
 
-pub trait UnaryOperationExecutor<T>: Fn(T) -> T {
+pub trait UnaryOperationExecutor<T>: Fn(T) -> T + Send + Sync {
     fn clone_box<'a>(&self) -> Box<dyn 'a + UnaryOperationExecutor<T>> where Self: 'a;
 }
 
-impl<T, F: Fn(T) -> T + Clone> UnaryOperationExecutor<T> for F {
+impl<T, F: Fn(T) -> T + Clone + Send + Sync> UnaryOperationExecutor<T> for F {
     fn clone_box<'a>(&self) -> Box<dyn 'a + UnaryOperationExecutor<T>> where Self: 'a, {
         Box::new(self.clone())
     }
@@ -46,4 +50,61 @@ impl<'a, T: 'a> Clone for Box<dyn 'a + UnaryOperationExecutor<T>> {
     fn clone(&self) -> Self {
         (**self).clone_box()
     }
-}
\ No newline at end of file
+}
+
+/// Deferred calculation of an operand
+pub type Thunk<'a, T> = &'a dyn Fn() -> Result<T, Token<T>>;
+
+pub(crate) type BoxedThunk<'a, T> = Box<dyn Fn() -> Result<T, Token<T>> + 'a>;
+
+pub trait LazyOperationExecutor<T: Clone>: Fn(&[Thunk<T>]) -> Result<T, Token<T>> + Send + Sync {
+    fn clone_box<'a>(&self) -> Box<dyn 'a + LazyOperationExecutor<T>> where Self: 'a;
+}
+
+impl<T: Clone, F: Fn(&[Thunk<T>]) -> Result<T, Token<T>> + Clone + Send + Sync> LazyOperationExecutor<T> for F {
+    fn clone_box<'a>(&self) -> Box<dyn 'a + LazyOperationExecutor<T>> where Self: 'a, {
+        Box::new(self.clone())
+    }
+}
+
+impl<'a, T: 'a + Clone> Clone for Box<dyn 'a + LazyOperationExecutor<T>> {
+    fn clone(&self) -> Self {
+        (**self).clone_box()
+    }
+}
+
+
+/// Executor over columns of operands, which writes one result per row to the output,
+/// e.g. `|args, out| for i in 0..out.len() { out[i] = args[0][i] + args[1][i] }`
+pub trait VectorOperationExecutor<T>: Fn(&[&[T]], &mut [T]) + Send + Sync {
+    fn clone_box<'a>(&self) -> Box<dyn 'a + VectorOperationExecutor<T>> where Self: 'a;
+}
+
+impl<T, F: Fn(&[&[T]], &mut [T]) + Clone + Send + Sync> VectorOperationExecutor<T> for F {
+    fn clone_box<'a>(&self) -> Box<dyn 'a + VectorOperationExecutor<T>> where Self: 'a, {
+        Box::new(self.clone())
+    }
+}
+
+impl<'a, T: 'a> Clone for Box<dyn 'a + VectorOperationExecutor<T>> {
+    fn clone(&self) -> Self {
+        (**self).clone_box()
+    }
+}
+
+
+/// Implementation of an operation.
+/// `Eager` receives calculated operands, `Lazy` receives thunks and calculates only needed ones.
+pub enum Executor<T: Clone> {
+    Eager(Box<dyn OperationExecutor<T>>),
+    Lazy(Box<dyn LazyOperationExecutor<T>>),
+}
+
+impl<T: 'static + Clone> Clone for Executor<T> {
+    fn clone(&self) -> Self {
+        return match self {
+            Executor::Eager(executor) => Executor::Eager(executor.clone()),
+            Executor::Lazy(executor) => Executor::Lazy(executor.clone()),
+        };
+    }
+}
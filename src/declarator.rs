@@ -1,42 +1,70 @@
-use crate::ast::AstNode;
-use crate::calculator::{BinaryOperationExecutor, OperationExecutor, UnaryOperationExecutor};
-use crate::operation::{Operation, OperationType};
-use crate::token::Token;
+use crate::ast::{apply, AstNode};
+use crate::calculator::{BinaryOperationExecutor, Executor, LazyOperationExecutor, OperationExecutor, UnaryOperationExecutor, VectorOperationExecutor};
+use crate::operation::{Associativity, Operation, OperationType, TypeSignature, ValueType};
+use crate::token::{Span, Token};
+use crate::flat_ast::{FlatAst, FlatAstBuilder, OperationId};
+use crate::trie::SignatureTrie;
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
 
 pub struct ExpressionDeclarator<T: Clone> {
-    pub(crate) operations: Vec<Operation<T>>,
+    /// Operations are shared with tokens and AST nodes, which refer to them
+    pub(crate) operations: Vec<Arc<Operation<T>>>,
+    /// Operations replaced by their changed copies, which tokens and trees built before still refer, with their indices
+    pub(crate) replaced: Vec<(Weak<Operation<T>>, usize)>,
+    /// Index of signatures of operations
+    pub(crate) signatures: SignatureTrie,
     pub(crate) handler: Box<dyn PrimitiveHandler<T>>,
+    pub(crate) separator: char,
+    pub(crate) variables: Vec<String>,
+    pub(crate) free_variables: bool,
+    pub(crate) implicit: Option<(String, u8)>,
+    /// Start and end markers of comments, line comments have no end marker
+    pub(crate) comments: Vec<(String, Option<String>)>,
+    pub(crate) max_depth: usize,
 }
 
-pub trait PrimitiveHandler<T> {
+pub trait PrimitiveHandler<T>: Send + Sync {
+    #[allow(clippy::ptr_arg, clippy::wrong_self_convention, clippy::result_unit_err)]
     fn from_string(&self, input: &String) -> Result<T, ()>;
     fn can_start_with(&self, input: String) -> bool;
-}
 
-#[derive(Clone)]
-enum State {
-    Empty,
-    Primitive,
-    Operation,
-    WhiteSpace,
+    /// The longest primitive at the start of input with its length in chars, and the length of the longest text,
+    /// which can start a primitive. The default one checks every prefix with `can_start_with` and `from_string`,
+    /// which is quadratic in the length of primitive. Handlers of long literals scan input once instead.
+    fn longest(&self, input: &[char]) -> (Option<(usize, T)>, usize) {
+        let mut text = String::new();
+        let mut longest = None;
+        let mut reach = 0;
+        for (index, char) in input.iter().enumerate() {
+            text.push(*char);
+            if !self.can_start_with(text.clone()) {
+                break;
+            }
+            reach = index + 1;
+            if let Ok(val) = self.from_string(&text) {
+                longest = Some((reach, val));
+            }
+        }
+
+        return (longest, reach);
+    }
+
+    /// Type of primitive value. Used for type checking of typed operations.
+    fn value_type(&self, _value: &T) -> Option<ValueType> {
+        return None;
+    }
 }
 
 struct Context<T: Clone> {
     out: Vec<Token<T>>,
-    state: State,
-    value: String,
-    pos: usize,
-}
-
-impl<T: 'static + Clone> Clone for Context<T> {
-    fn clone(&self) -> Self {
-        return Context {
-            out: self.out.clone(),
-            state: self.state.clone(),
-            value: self.value.clone(),
-            pos: self.pos,
-        };
-    }
+    /// Byte offsets of chars of input and the length of input
+    offsets: Vec<usize>,
+    /// Lines and columns of chars of input and of its end
+    locations: Vec<(usize, usize)>,
+    /// Start and length of the longest text, which could be continued to a primitive or signature,
+    /// when a shorter match was taken. It's reported instead of the failed text inside it.
+    attempt: Option<(usize, usize)>,
 }
 
 pub const LOWEST_ORDER: u8 = 10;
@@ -46,14 +74,121 @@ pub const HIGH_ORDER: u8 = 40;
 pub const HIGHEST_ORDER: u8 = 50;
 pub const ULTIMATE_ORDER: u8 = u8::MAX;
 
+/// Default maximum nesting of lazy operations in AST.
+/// Built-in calculators calculate it on a 2 MB thread stack in debug builds with a margin of 4 times.
+pub const DEFAULT_MAX_DEPTH: usize = 100;
+
 impl<T: 'static + Clone> ExpressionDeclarator<T> {
     pub fn new(handler: Box<dyn PrimitiveHandler<T>>) -> ExpressionDeclarator<T> {
         return ExpressionDeclarator {
-            operations: Vec::<Operation<T>>::new(),
+            operations: Vec::new(),
+            replaced: Vec::new(),
+            signatures: SignatureTrie::new(),
             handler,
+            separator: ',',
+            variables: Vec::new(),
+            free_variables: false,
+            implicit: None,
+            comments: Vec::new(),
+            max_depth: DEFAULT_MAX_DEPTH,
         };
     }
 
+    /// Declared operations in order of declaration, e.g. for help output
+    pub fn operations(&self) -> &[Arc<Operation<T>>] {
+        return &self.operations;
+    }
+
+    /// Set separator of function arguments. Default is ','
+    pub fn set_separator(&mut self, separator: char) {
+        self.separator = separator;
+    }
+
+    /// Declare variable, which value is provided on calculation
+    pub fn add_variable(&mut self, name: String) {
+        self.variables.push(name);
+    }
+
+    /// Treat every unknown identifier as a variable, e.g. `a & b` for `a` and `b`.
+    /// Identifier starts with a letter or '_' and continues with letters, digits or '_'.
+    pub fn set_free_variables(&mut self, enabled: bool) {
+        self.free_variables = enabled;
+    }
+
+    /// Insert declared infix operation between adjacent operands, e.g. `2pi`, `3(x+1)` or `(a+b)(a-b)` for "*".
+    /// Inserted operation has its own priority, e.g. `1/2x` is read as `1/(2x)` when it's higher than priority of "/"
+    /// and as `(1/2)x` otherwise.
+    pub fn set_implicit_infix(&mut self, signature: String, order: u8) {
+        self.implicit = Some((signature, order));
+    }
+
+    /// Set maximum nesting of lazy operations in AST, e.g. `a & b & c` has two levels.
+    /// Deeper expressions are rejected with `Token::TooDeep` on building of AST, as every level of lazy operations
+    /// takes the call stack on calculation. Eager operations are calculated with explicit stack and aren't limited.
+    pub fn set_max_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
+    }
+
+    /// Recognize comments from marker to the end of line, e.g. "#" or "//"
+    pub fn add_line_comment(&mut self, start: String) {
+        self.comments.push((start, None));
+    }
+
+    /// Recognize comments between markers, e.g. "/*" and "*/"
+    pub fn add_block_comment(&mut self, start: String, end: String) {
+        self.comments.push((start, Some(end)));
+    }
+
+    /// Add prepared operation
+    pub fn add_operation(&mut self, operation: Operation<T>) {
+        let index = self.operations.len();
+        self.signatures.add_operation(&operation.signature, operation.op_type.clone(), index);
+        if let Some(delimiter) = &operation.delimiter {
+            self.signatures.add_delimiter(delimiter);
+        }
+        self.operations.push(Arc::new(operation));
+    }
+
+    /// Set executor over columns for batch calculation to operations with the signature and type.
+    /// Tokens and trees, which were built before, keep operations without it.
+    pub fn set_vectorized(&mut self, signature: String, op_type: OperationType, executor: Box<dyn VectorOperationExecutor<T>>) {
+        self.update(&signature, op_type, |operation| operation.vectorized = Some(executor.clone()));
+    }
+
+    /// Mark operations with the signature and type as impure, e.g. random numbers,
+    /// so their equal subexpressions are not shared. Tokens and trees, which were built before, keep pure operations.
+    pub fn set_impure(&mut self, signature: String, op_type: OperationType) {
+        self.update(&signature, op_type, |operation| operation.pure = false);
+    }
+
+    fn update(&mut self, signature: &str, op_type: OperationType, change: impl Fn(&mut Operation<T>)) {
+        let indices: Vec<usize> = self.signatures.operations(signature).iter()
+            .filter(|(it, _)| *it == op_type)
+            .map(|(_, index)| *index)
+            .collect();
+        for index in indices {
+            if Arc::strong_count(&self.operations[index]) > 1 {
+                self.replaced.push((Arc::downgrade(&self.operations[index]), index));
+            }
+            change(Arc::make_mut(&mut self.operations[index]));
+        }
+    }
+
+    /// Declare alternative signature for all operations with the signature, e.g. "×" for "*"
+    pub fn add_alias(&mut self, alias: String, signature: String) {
+        let aliases: Vec<Operation<T>> = self.operations.iter()
+            .filter(|it| it.signature == signature)
+            .map(|it| {
+                let mut operation = Operation::clone(it);
+                operation.signature = alias.clone();
+                operation
+            })
+            .collect();
+        for operation in aliases {
+            self.add_operation(operation);
+        }
+    }
+
     pub fn add(
         &mut self,
         signature: String,
@@ -63,14 +198,63 @@ impl<T: 'static + Clone> ExpressionDeclarator<T> {
         operands: u8,
         order: u8,
     ) {
-        self.operations.push(Operation {
-            signature,
-            description,
-            op_type,
-            operands,
-            priority: order,
-            executor,
-        });
+        self.add_operation(Operation::new(signature, description, op_type, Executor::Eager(executor), operands, order));
+    }
+
+    /// Add operation with typed operands and result.
+    /// Operations with the same signature and type can be overloaded by operand types,
+    /// but the first declared one defines priority for all of them.
+    pub fn add_typed(
+        &mut self,
+        signature: String,
+        description: String,
+        op_type: OperationType,
+        executor: Executor<T>,
+        types: TypeSignature,
+        order: u8,
+    ) {
+        let mut operation = Operation::new(signature, description, op_type, executor, types.operands.len() as u8, order);
+        operation.types = Some(types);
+        self.add_operation(operation);
+    }
+
+    /// Add operation, which receives operands as thunks and calculates only needed ones
+    pub fn add_lazy(
+        &mut self,
+        signature: String,
+        description: String,
+        op_type: OperationType,
+        executor: Box<dyn LazyOperationExecutor<T>>,
+        operands: u8,
+        order: u8,
+    ) {
+        self.add_operation(Operation::new(signature, description, op_type, Executor::Lazy(executor), operands, order));
+    }
+
+    /// Add function with several arguments, e.g. `max(x, y)`.
+    /// Arguments must be enclosed in parenthesis and divided by separator.
+    pub fn add_function(
+        &mut self,
+        signature: String,
+        description: String,
+        executor: Box<dyn OperationExecutor<T>>,
+        operands: u8,
+    ) {
+        self.add(signature, description, OperationType::Prefix, executor, operands, ULTIMATE_ORDER);
+    }
+
+    /// Add ternary operation, e.g. `x ? y : z`, where "?" is a signature and ":" is a delimiter
+    pub fn add_ternary(
+        &mut self,
+        signature: String,
+        delimiter: String,
+        description: String,
+        executor: Box<dyn LazyOperationExecutor<T>>,
+        order: u8,
+    ) {
+        let mut operation = Operation::new(signature, description, OperationType::Ternary, Executor::Lazy(executor), 3, order);
+        operation.delimiter = Some(delimiter);
+        self.add_operation(operation);
     }
 
     pub fn add_prefix(
@@ -101,36 +285,37 @@ impl<T: 'static + Clone> ExpressionDeclarator<T> {
         op_type: OperationType,
         order: u8,
     ) {
-        self.operations.push(Operation {
-            signature,
-            description,
-            op_type,
-            operands: 1,
-            priority: order,
-            executor: Box::new(move |operands| { executor(operands[0].clone()) }),
-        });
+        self.add(signature, description, op_type, Box::new(move |operands| { executor(operands[0].clone()) }), 1, order);
+    }
+
+    /// Value is moved to executor, so it must be `Send + Sync` like executors
+    pub fn add_constant(&mut self, signature: String, description: String, value: T) where T: Send + Sync {
+        self.add(signature, description, OperationType::Constant, Box::new(move |_| { value.clone() }), 0, ULTIMATE_ORDER);
     }
 
-    pub fn add_constant(&mut self, signature: String, description: String, value: T) {
-        self.operations.push(Operation {
+    pub fn add_infix(&mut self, signature: String, description: String, executor: Box<dyn BinaryOperationExecutor<T>>, order: u8) {
+        self.add(
             signature,
             description,
-            op_type: OperationType::Constant,
-            operands: 0,
-            priority: ULTIMATE_ORDER,
-            executor: Box::new(move |_| { value.clone() }),
-        });
+            OperationType::Infix,
+            Box::new(move |operands| { executor(operands[0].clone(), operands[1].clone()) }),
+            2,
+            order,
+        );
     }
 
-    pub fn add_infix(&mut self, signature: String, description: String, executor: Box<dyn BinaryOperationExecutor<T>>, order: u8) {
-        self.operations.push(Operation {
+    /// Helper function for right associative infix operations, e.g. `2^3^2 = 2^(3^2)`
+    pub fn add_right_infix(&mut self, signature: String, description: String, executor: Box<dyn BinaryOperationExecutor<T>>, order: u8) {
+        let mut operation = Operation::new(
             signature,
             description,
-            op_type: OperationType::Infix,
-            operands: 2,
-            priority: order,
-            executor: Box::new(move |operands| { executor(operands[0].clone(), operands[1].clone()) }),
-        });
+            OperationType::Infix,
+            Executor::Eager(Box::new(move |operands: &[T]| { executor(operands[0].clone(), operands[1].clone()) })),
+            2,
+            order,
+        );
+        operation.associativity = Associativity::Right;
+        self.add_operation(operation);
     }
 }
 
@@ -141,29 +326,26 @@ impl<T: 'static + Clone> ExpressionDeclarator<T> {
         return ast.calculate();
     }
 
+    /// Calculate expression with values of variables
+    pub fn calculate_with(&self, input: &str, variables: &HashMap<String, T>) -> Result<T, Token<T>> {
+        let ast = self.build_ast(input)?;
+
+        return ast.calculate_with(variables);
+    }
+
+    /// Split input into tokens by the longest match.
+    /// At every position the longest variable, primitive or operation signature is taken,
+    /// so the text is read back to the last complete match when a longer one can't be completed.
     pub fn tokenize(&self, input: &str) -> Result<Vec<Token<T>>, Token<T>> {
-        let mut context = Context::new();
-
-        for entry in input.chars().into_iter().enumerate() {
-            let pos = entry.0;
-            let val = entry.1;
-
-            match context.state {
-                State::Empty => context.init_token_creation(pos, val, self)?,
-                _ if context.is_suitable_for_current_state(val, self) => context.add_symbol(val),
-                State::Primitive if context.can_add_to_operation(val, self) => context.mutate_to_operation(val),
-                _ => {
-                    context.collect_token(self)?;
-                    context.init_token_creation(pos, val, self)?
-                }
-            }
-        }
+        let mut context = Context::new(input);
+        let chars: Vec<char> = input.chars().collect();
+        let mut pos = 0;
 
-        if !context.is_empty() {
-            context.collect_token(self)?;
+        while pos < chars.len() {
+            pos += context.next_token(&chars, pos, self)?;
         }
 
-        return Result::Ok(context.get_tokens().clone());
+        return Result::Ok(context.out);
     }
 
     pub fn build_ast(&self, input: &str) -> Result<AstNode<T>, Token<T>> {
@@ -172,60 +354,239 @@ impl<T: 'static + Clone> ExpressionDeclarator<T> {
         return self.build_ast_from_tokens(&tokens);
     }
 
-    fn can_be_operation(&self, text: &String) -> bool {
-        for operation in self.operations.iter() {
-            if operation.signature.starts_with(text) {
-                return true;
-            }
+    fn make_by_type(&self, value: &str, op_type: OperationType) -> Option<&Arc<Operation<T>>> {
+        return self.signatures.operation(value, &op_type).map(|index| &self.operations[index]);
+    }
+
+    /// Variable name, starting at given position
+    fn variable_at(&self, chars: &[char], pos: usize) -> Option<String> {
+        if !(chars[pos].is_alphabetic() || chars[pos] == '_') {
+            return None;
+        }
+
+        let name: String = chars[pos..].iter()
+            .take_while(|it| it.is_alphanumeric() || **it == '_')
+            .collect();
+
+        if self.variables.contains(&name) {
+            return Some(name);
         }
+        if !self.free_variables
+            || !self.signatures.operations(&name).is_empty()
+            || self.handler.from_string(&name).is_ok() {
+            return None;
+        }
+
+        return Some(name);
+    }
 
-        return false;
+    fn is_delimiter(&self, value: &str) -> bool {
+        return self.signatures.is_delimiter(value);
     }
 
-    fn make_by_type(&self, value: &String, op_type: OperationType) -> Option<&Operation<T>> {
-        for operation in self.operations.iter() {
-            if operation.signature == *value && operation.op_type == op_type {
-                return Option::Some(&operation);
+    /// Type of the value, produced by AST node, if it is known
+    pub fn type_of(&self, node: &AstNode<T>) -> Option<ValueType> {
+        return match node {
+            AstNode::Primitive { val, token } => match token {
+                Token::Operation { val: op, .. } => op.types.as_ref().map(|types| types.result),
+                _ => self.handler.value_type(val),
+            },
+            AstNode::Unary { op, .. } => op.types.as_ref().map(|types| types.result),
+            AstNode::Binary { op, .. } => op.types.as_ref().map(|types| types.result),
+            AstNode::Nary { op, .. } => op.types.as_ref().map(|types| types.result),
+            AstNode::Variable { .. } => None,
+        };
+    }
+
+    fn resolve_overload(&self, op: Arc<Operation<T>>, types: &[Option<ValueType>]) -> Option<Arc<Operation<T>>> {
+        if op.accepts(types) {
+            return Some(op);
+        }
+
+        for (op_type, index) in self.signatures.operations(&op.signature) {
+            let operation = &self.operations[*index];
+            if *op_type == op.op_type && operation.accepts(types) {
+                return Some(operation.clone());
             }
         }
 
         return None;
     }
+
+    /// Index of declared operation. Implicit operation has its own priority and isn't shared with the declarator,
+    /// so it is matched by signature, type and types of operands. Operations of other declarators are not found.
+    pub(crate) fn operation_id(&self, op: &Operation<T>) -> Option<OperationId> {
+        let candidates = self.signatures.operations(&op.signature);
+        if let Some((_, index)) = candidates.iter().find(|(_, index)| std::ptr::eq(&*self.operations[*index], op)) {
+            return Some(*index as OperationId);
+        }
+        if let Some((_, index)) = self.replaced.iter().find(|(replaced, _)| std::ptr::eq(replaced.as_ptr(), op)) {
+            return Some(*index as OperationId);
+        }
+
+        let implicit = match &self.implicit {
+            Some((signature, order)) => *signature == op.signature && *order == op.priority && op.op_type == OperationType::Infix,
+            None => false,
+        };
+        if !implicit {
+            return None;
+        }
+        return candidates.iter()
+            .find(|(op_type, index)| *op_type == op.op_type && self.operations[*index].types == op.types)
+            .map(|(_, index)| *index as OperationId);
+    }
+}
+
+/// Receiver of nodes, made by parser, e.g. boxed `AstNode` or `FlatAst`
+pub(crate) trait AstBuilder<T: Clone> {
+    type Node;
+
+    fn primitive(&mut self, val: T, token: &Token<T>) -> Self::Node;
+    fn variable(&mut self, name: &str, token: &Token<T>) -> Self::Node;
+    /// Value of constant operation, which is calculated on building
+    fn constant(&mut self, val: T, token: &Token<T>) -> Result<Self::Node, Token<T>>;
+    fn operation(&mut self, op: Arc<Operation<T>>, operands: Vec<Self::Node>, token: &Token<T>) -> Result<Self::Node, Token<T>>;
+    /// Type of the value, produced by node, if it is known
+    fn type_of(&self, node: &Self::Node) -> Option<ValueType>;
+    fn token(&self, node: &Self::Node) -> Token<T>;
+}
+
+/// Builder of boxed `AstNode`
+struct TreeBuilder<'a, T: Clone> {
+    declarator: &'a ExpressionDeclarator<T>,
+}
+
+impl<T: 'static + Clone> AstBuilder<T> for TreeBuilder<'_, T> {
+    type Node = AstNode<T>;
+
+    fn primitive(&mut self, val: T, token: &Token<T>) -> AstNode<T> {
+        return AstNode::Primitive { val, token: token.clone() };
+    }
+
+    fn variable(&mut self, name: &str, token: &Token<T>) -> AstNode<T> {
+        return AstNode::Variable { name: name.to_string(), token: token.clone() };
+    }
+
+    fn constant(&mut self, val: T, token: &Token<T>) -> Result<AstNode<T>, Token<T>> {
+        return Result::Ok(AstNode::Primitive { val, token: token.clone() });
+    }
+
+    fn operation(&mut self, op: Arc<Operation<T>>, mut operands: Vec<AstNode<T>>, token: &Token<T>) -> Result<AstNode<T>, Token<T>> {
+        let token = token.clone();
+        return Result::Ok(match operands.len() {
+            1 => AstNode::Unary { op, p1: Box::new(operands.pop().unwrap()), token },
+            2 => {
+                let p2 = Box::new(operands.pop().unwrap());
+                AstNode::Binary { op, p1: Box::new(operands.pop().unwrap()), p2, token }
+            }
+            _ => AstNode::Nary { op, args: operands, token },
+        });
+    }
+
+    fn type_of(&self, node: &AstNode<T>) -> Option<ValueType> {
+        return self.declarator.type_of(node);
+    }
+
+    fn token(&self, node: &AstNode<T>) -> Token<T> {
+        return node.token().clone();
+    }
 }
 
 impl<T: 'static + Clone> ExpressionDeclarator<T> {
     pub fn build_ast_from_tokens(&self, tokens: &Vec<Token<T>>) -> Result<AstNode<T>, Token<T>> {
+        return self.parse(tokens, &mut TreeBuilder { declarator: self });
+    }
+
+    /// Build AST in flat vectors, which refers to operations of this declarator
+    pub fn build_flat_ast(&self, input: &str) -> Result<FlatAst<T>, Token<T>> {
+        let tokens = self.tokenize(input)?;
+
+        return self.build_flat_ast_from_tokens(&tokens);
+    }
+
+    pub fn build_flat_ast_from_tokens(&self, tokens: &Vec<Token<T>>) -> Result<FlatAst<T>, Token<T>> {
+        let mut builder = FlatAstBuilder::new(self);
+        let root = self.parse(tokens, &mut builder)?;
+
+        return Result::Ok(builder.finish(root));
+    }
+
+    /// Build nodes from tokens by shunting yard algorithm
+    pub(crate) fn parse<B: AstBuilder<T>>(&self, tokens: &Vec<Token<T>>, builder: &mut B) -> Result<B::Node, Token<T>> {
+        let with_implicit;
+        let tokens = match self.implicit {
+            Some(_) => {
+                with_implicit = self.insert_implicit(tokens);
+                &with_implicit
+            }
+            None => tokens,
+        };
+
         let mut stack: Vec<Token<T>> = Vec::new();
-        let mut operands: Vec<AstNode<T>> = Vec::new();
+        // Nodes with nesting of lazy operations in them
+        let mut operands: Vec<(B::Node, usize)> = Vec::new();
+        // Number of arguments inside each open parenthesis
+        let mut arguments: Vec<usize> = Vec::new();
 
         for token in tokens {
             match token {
-                Token::Primitive { pos: _pos, val, .. } => operands.push(AstNode::Primitive { val: val.clone(), token: token.clone() }),
-                Token::Open { .. } => stack.push(token.clone()),
+                Token::Primitive { val, .. } => operands.push((builder.primitive(val.clone(), token), 0)),
+                Token::Variable { val, .. } => operands.push((builder.variable(val, token), 0)),
+                Token::Open { .. } => {
+                    stack.push(token.clone());
+                    arguments.push(1);
+                }
                 Token::Close { .. } => {
+                    self.reduce_until_open(builder, &mut operands, &mut stack, token)?;
+                    stack.pop();
+                    let count = arguments.pop().unwrap();
+                    let function = match stack.last() {
+                        Some(Token::Operation { val, .. }) if val.is_function() => Some(val.operands as usize),
+                        _ => None,
+                    };
+                    match function {
+                        Some(expected) if expected == count => self.reduce(builder, &mut operands, &mut stack)?,
+                        Some(_) => return Result::Err(stack.pop().unwrap()),
+                        None if count != 1 => return Result::Err(token.clone()),
+                        None => (),
+                    }
+                }
+                Token::Separator { val, .. } if *val == self.separator.to_string() => {
+                    self.reduce_until_open(builder, &mut operands, &mut stack, token)?;
+                    *arguments.last_mut().unwrap() += 1;
+                }
+                Token::Separator { .. } => {
                     loop {
-                        let last = stack.last();
-                        if last.is_none() {
-                            return Result::Err(token.clone());
-                        }
-                        if matches!(stack.last().unwrap(), Token::Open {..}) {
-                            break;
+                        match stack.last() {
+                            Some(Token::Operation { val, .. }) if val.op_type == OperationType::Ternary => break,
+                            Some(Token::Open { .. }) | None => return Result::Err(token.clone()),
+                            _ => self.reduce(builder, &mut operands, &mut stack)?,
                         }
-                        self.make_node(&mut operands, stack.pop().unwrap())?;
-                    };
-                    stack.pop();
+                    }
+                    stack.push(token.clone())
                 }
-                Token::Operation { pos: _, val } => {
-                    while !stack.is_empty() {
-                        let last_op = match stack.last().unwrap().clone() {
-                            Token::Operation { pos: _, val } => val,
+                Token::Operation { val, .. } => {
+                    // Only operations with left operand can complete the previous ones
+                    let has_left_operand = matches!(val.op_type, OperationType::Infix | OperationType::Postfix | OperationType::Ternary);
+                    while has_left_operand && !stack.is_empty() {
+                        let last_priority = match stack.last().unwrap() {
+                            Token::Operation { val, .. } if val.op_type == OperationType::Ternary => break,
+                            Token::Operation { val, .. } => val.priority,
+                            Token::Separator { .. } => match &stack[stack.len() - 2] {
+                                Token::Operation { val, .. } => val.priority,
+                                _ => return Result::Err(stack.last().unwrap().clone())
+                            },
                             Token::Open { .. } => break,
                             _ => return Result::Err(stack.last().unwrap().clone())
                         };
-                        if last_op.priority <= val.priority {
+                        let completes = match val.associativity {
+                            Associativity::Left => last_priority >= val.priority,
+                            Associativity::Right => last_priority > val.priority,
+                        };
+                        if !completes {
                             break;
                         }
-                        self.make_node(&mut operands, stack.pop().unwrap())?;
+                        self.reduce(builder, &mut operands, &mut stack)?;
                     }
                     stack.push(token.clone())
                 }
@@ -234,176 +595,286 @@ impl<T: 'static + Clone> ExpressionDeclarator<T> {
         }
 
         while stack.last().is_some() {
-            self.make_node(&mut operands, stack.pop().unwrap())?;
+            self.reduce(builder, &mut operands, &mut stack)?;
         };
 
-        return Result::Ok(operands.pop().unwrap());
+        if operands.len() > 1 {
+            return Result::Err(builder.token(&operands[1].0));
+        }
+
+        return match operands.pop() {
+            Some((ast, _)) => Result::Ok(ast),
+            None => Result::Err(Token::Unknown { span: Span::default(), val: String::new() }),
+        };
     }
 
-    fn make_node(&self, operands: &mut Vec<AstNode<T>>, token: Token<T>) -> Result<(), Token<T>> {
-        let copy = token.clone();
-        let op = match token {
-            Token::Operation { pos: _, val } => val.clone(),
-            _ => return Result::Err(token)
+    /// Insert implicit operation between tokens, which end and start operands
+    fn insert_implicit(&self, tokens: &[Token<T>]) -> Vec<Token<T>> {
+        let (signature, order) = self.implicit.clone().unwrap();
+        let operation = match self.make_by_type(&signature, OperationType::Infix) {
+            Some(operation) => {
+                let mut operation = Operation::clone(operation);
+                operation.priority = order;
+                Arc::new(operation)
+            }
+            None => return tokens.to_vec(),
         };
 
-        if op.operands == 0 {
-            operands.push(AstNode::Primitive { val: (&op.executor)(Vec::new()), token: copy })
-        } else if op.operands == 1 {
-            let op_right = operands.pop().unwrap();
-            operands.push(AstNode::Unary { op, p1: Box::new(op_right), token: copy })
-        } else {
-            let op_right = operands.pop().unwrap();
-            let op_left = operands.pop();
-            operands.push(AstNode::Binary { op, p1: Box::new(op_left.unwrap()), p2: Box::new(op_right), token: copy })
+        let mut result = Vec::with_capacity(tokens.len());
+        let mut previous_ends_operand = false;
+        for token in tokens {
+            if token.is_ignored() {
+                result.push(token.clone());
+                continue;
+            }
+            if previous_ends_operand && token.starts_operand() {
+                result.push(Token::Operation { span: token.get_span().start_point(), val: operation.clone() });
+            }
+            previous_ends_operand = token.ends_operand();
+            result.push(token.clone());
         }
 
-        return Result::Ok(());
+        return result;
     }
-}
 
-impl<T: 'static + Clone> Context<T> {
-    pub(crate) fn new() -> Context<T> {
-        return Context { out: Vec::new(), state: State::Empty, value: String::new(), pos: 0 };
+    /// Make nodes from stack until open parenthesis
+    fn reduce_until_open<B: AstBuilder<T>>(&self, builder: &mut B, operands: &mut Vec<(B::Node, usize)>, stack: &mut Vec<Token<T>>, token: &Token<T>) -> Result<(), Token<T>> {
+        loop {
+            match stack.last() {
+                None => return Result::Err(token.clone()),
+                Some(Token::Open { .. }) => return Result::Ok(()),
+                _ => self.reduce(builder, operands, stack)?,
+            }
+        }
     }
 
-    fn get_tokens(&self) -> &Vec<Token<T>> {
-        return &self.out;
+    /// Make node from the top of the stack.
+    /// Delimiter of ternary operation is taken together with the operation itself.
+    fn reduce<B: AstBuilder<T>>(&self, builder: &mut B, operands: &mut Vec<(B::Node, usize)>, stack: &mut Vec<Token<T>>) -> Result<(), Token<T>> {
+        let token = stack.pop().unwrap();
+        return match token {
+            Token::Separator { .. } => self.make_node(builder, operands, stack.pop().unwrap()),
+            Token::Operation { ref val, .. } if val.op_type == OperationType::Ternary => Result::Err(token),
+            _ => self.make_node(builder, operands, token),
+        };
     }
 
-    fn init_token_creation(&mut self, pos: usize, val: char, expr_calculator: &ExpressionDeclarator<T>) -> Result<(), Token<T>> {
-        self.value = String::new();
-        self.state = State::Empty;
+    fn make_node<B: AstBuilder<T>>(&self, builder: &mut B, operands: &mut Vec<(B::Node, usize)>, token: Token<T>) -> Result<(), Token<T>> {
+        let copy = token.clone();
+        let op = match token {
+            Token::Operation { val, .. } => val.clone(),
+            _ => return Result::Err(token)
+        };
+
+        let count = op.operands as usize;
+        if operands.len() < count {
+            return Result::Err(copy);
+        }
+        let args = operands.split_off(operands.len() - count);
+        let nested = args.iter().map(|(_, depth)| *depth).max().unwrap_or(0);
+        let depth = if matches!(op.executor, Executor::Lazy(_)) { nested + 1 } else { nested };
+        if depth > self.max_depth {
+            return Result::Err(Token::TooDeep { span: copy.get_span(), val: copy.get_value() });
+        }
+        let args: Vec<B::Node> = args.into_iter().map(|(node, _)| node).collect();
 
-        match val {
-            ' ' => self.init_whitespace(pos),
-            '(' => self.add_token(Token::Open { pos })?,
-            ')' => self.add_token(Token::Close { pos })?,
-            it if self.can_add_to_primitive(it, expr_calculator) => self.init_primitive(it, pos),
-            it if expr_calculator.can_be_operation(&it.to_string()) => self.init_operation(it, pos),
-            _ => return Result::Err(Token::Unknown { pos, val: val.to_string() })
+        if count == 0 {
+            operands.push((builder.constant(apply(&op, &[], &HashMap::new())?, &copy)?, 0));
+            return Result::Ok(());
         }
 
+        let types: Vec<Option<ValueType>> = args.iter().map(|it| builder.type_of(it)).collect();
+        let op = match self.resolve_overload(op, &types) {
+            Some(op) => op,
+            None => return Result::Err(copy),
+        };
+        operands.push((builder.operation(op, args, &copy)?, depth));
+
         return Result::Ok(());
     }
+}
 
-    fn collect_token(&mut self, expr_calculator: &ExpressionDeclarator<T>) -> Result<(), Token<T>> {
-        let token = match self.state {
-            State::Operation => {
-                let op = self.value.clone();
-                let op_type = if self.suitable_for_prefix(&op, expr_calculator) {
-                    OperationType::Prefix
-                } else if self.suitable_for_postfix(&op, expr_calculator) {
-                    OperationType::Postfix
-                } else if self.suitable_for_infix(&op, expr_calculator) {
-                    OperationType::Infix
-                } else if expr_calculator.make_by_type(&op, OperationType::Constant).is_some() {
-                    OperationType::Constant
-                } else {
-                    return Result::Err(Token::Unknown { pos: self.pos, val: self.value.clone() });
-                };
-
-                let operation = expr_calculator.make_by_type(&op, op_type);
-
-                Token::Operation { pos: self.pos, val: Box::new(operation.unwrap().clone()) }
+impl<T: 'static + Clone> Context<T> {
+    pub(crate) fn new(input: &str) -> Context<T> {
+        let mut offsets: Vec<usize> = input.char_indices().map(|(offset, _)| offset).collect();
+        offsets.push(input.len());
+
+        let mut locations = Vec::with_capacity(offsets.len());
+        let (mut line, mut column) = (1, 1);
+        for char in input.chars() {
+            locations.push((line, column));
+            if char == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
             }
-            State::WhiteSpace => self.to_whitespace_token(),
-            State::Empty => return Result::Ok(()),
-            State::Primitive => self.to_primitive(expr_calculator)?
-        };
-        self.add_token(token)?;
+        }
+        locations.push((line, column));
 
-        Result::Ok(())
+        return Context { out: Vec::new(), offsets, locations, attempt: None };
     }
 
-    fn add_token(&mut self, token: Token<T>) -> Result<(), Token<T>> {
-        self.out.push(token);
-
-        return Result::Ok(());
+    /// Span of chars from start
+    fn span(&self, start: usize, chars: usize) -> Span {
+        return Span {
+            byte_start: self.offsets[start],
+            byte_end: self.offsets[start + chars],
+            char_start: start,
+            char_end: start + chars,
+            line: self.locations[start].0,
+            column: self.locations[start].1,
+        };
     }
 
-    fn take_last(&self) -> Option<Token<T>> {
-        if self.out.is_empty() {
-            return None;
+    /// Add token at position and return its length in chars
+    fn next_token(&mut self, chars: &[char], pos: usize, expr_calculator: &ExpressionDeclarator<T>) -> Result<usize, Token<T>> {
+        if let Some(length) = self.comment_at(chars, pos, expr_calculator)? {
+            self.add_token(Token::Comment { span: self.span(pos, length), val: chars[pos..pos + length].iter().collect() })?;
+            return Result::Ok(length);
         }
-        for x in self.out.clone().iter().rev() {
-            if !matches!(x, Token::WhiteSpace {..}) {
-                return Some(x.clone());
+
+        let span = self.span(pos, 1);
+        match chars[pos] {
+            it if it.is_whitespace() => {
+                let length = chars[pos..].iter().take_while(|it| it.is_whitespace()).count();
+                self.add_token(Token::WhiteSpace { span: self.span(pos, length), val: chars[pos..pos + length].iter().collect() })?;
+                return Result::Ok(length);
             }
+            '(' => self.add_token(Token::Open { span })?,
+            ')' => self.add_token(Token::Close { span })?,
+            it if it == expr_calculator.separator => self.add_token(Token::Separator { span, val: it.to_string() })?,
+            _ => return self.next_word(chars, pos, expr_calculator),
         }
 
-        return None;
+        return Result::Ok(1);
     }
 
-    fn can_add_to_primitive(&self, char: char, expr_calculator: &ExpressionDeclarator<T>) -> bool {
-        let mut test = String::from(&self.value);
-        test.push(char);
+    /// Add the longest variable, primitive or operation at position.
+    /// Ties are resolved in this order, e.g. declared variable `e` shadows constant `e`.
+    fn next_word(&mut self, chars: &[char], pos: usize, expr_calculator: &ExpressionDeclarator<T>) -> Result<usize, Token<T>> {
+        let mut best: Option<(usize, Token<T>)> = None;
 
-        return expr_calculator.handler.can_start_with(test);
+        if let Some(name) = expr_calculator.variable_at(chars, pos) {
+            let length = name.chars().count();
+            best = Some((length, Token::Variable { span: self.span(pos, length), val: name }));
+        }
+
+        let (primitive, primitive_reach) = self.longest_primitive(chars, pos, expr_calculator);
+        if let Some((length, val, original)) = primitive {
+            if !best.as_ref().is_some_and(|(longest, _)| length <= *longest) {
+                best = Some((length, Token::Primitive { span: self.span(pos, length), val, original }));
+            }
+        }
+
+        let (signatures, signature_reach) = self.signatures_at(chars, pos, expr_calculator);
+        for signature in signatures.into_iter().rev() {
+            let length = signature.chars().count();
+            if best.as_ref().is_some_and(|(longest, _)| length <= *longest) {
+                break;
+            }
+            if let Some(token) = self.operation_token(signature, self.span(pos, length), expr_calculator) {
+                best = Some((length, token));
+                break;
+            }
+        }
+
+        let reach = primitive_reach.max(signature_reach);
+        return match best {
+            Some((length, token)) => {
+                if reach > length {
+                    self.attempt = Some((pos, reach));
+                }
+                self.add_token(token)?;
+                Result::Ok(length)
+            }
+            None => Result::Err(self.unknown(chars, pos, reach.max(1))),
+        };
     }
 
-    fn can_add_to_operation(&self, char: char, expr_calculator: &ExpressionDeclarator<T>) -> bool {
-        let mut test = String::from(&self.value);
-        test.push(char);
+    /// The longest complete primitive at position and the length of the longest text, which can start a primitive
+    fn longest_primitive(&self, chars: &[char], pos: usize, expr_calculator: &ExpressionDeclarator<T>) -> (Option<(usize, T, String)>, usize) {
+        let (longest, reach) = expr_calculator.handler.longest(&chars[pos..]);
 
-        return expr_calculator.can_be_operation(&test);
+        return (longest.map(|(length, val)| (length, val, chars[pos..pos + length].iter().collect())), reach);
     }
 
-    fn mutate_to_operation(&mut self, value: char) {
-        self.state = State::Operation;
-        self.add_symbol(value)
+    /// Declared signatures and delimiters at position from the shortest to the longest,
+    /// and the length of the longest text, which can start one
+    fn signatures_at(&self, chars: &[char], pos: usize, expr_calculator: &ExpressionDeclarator<T>) -> (Vec<String>, usize) {
+        let (lengths, reach) = expr_calculator.signatures.matches(&chars[pos..]);
+        let found = lengths.into_iter().map(|length| chars[pos..pos + length].iter().collect()).collect();
+
+        return (found, reach);
     }
 
-    fn is_suitable_for_current_state(&self, char: char, expr_calculator: &ExpressionDeclarator<T>) -> bool {
-        return match self.state {
-            State::Empty => false,
-            State::Primitive => self.can_add_to_primitive(char, expr_calculator),
-            State::Operation => self.can_add_to_operation(char, expr_calculator),
-            State::WhiteSpace => char == ' '
+    /// Operation or delimiter token for signature, which suits the previous tokens
+    fn operation_token(&self, op: String, span: Span, expr_calculator: &ExpressionDeclarator<T>) -> Option<Token<T>> {
+        let op_type = if self.suitable_for_prefix(&op, expr_calculator) {
+            OperationType::Prefix
+        } else if self.suitable_for_postfix(&op, expr_calculator) {
+            OperationType::Postfix
+        } else if self.suitable_for_infix(&op, expr_calculator, OperationType::Infix) {
+            OperationType::Infix
+        } else if self.suitable_for_infix(&op, expr_calculator, OperationType::Ternary) {
+            OperationType::Ternary
+        } else if expr_calculator.make_by_type(&op, OperationType::Constant).is_some() {
+            OperationType::Constant
+        } else if expr_calculator.is_delimiter(&op) {
+            return Some(Token::Separator { span, val: op });
+        } else {
+            return None;
         };
-    }
 
-    fn init_whitespace(&mut self, pos: usize) {
-        self.state = State::WhiteSpace;
-        self.value = String::from(' ');
-        self.pos = pos;
-    }
+        let operation = expr_calculator.make_by_type(&op, op_type);
 
-    fn init_primitive(&mut self, val: char, pos: usize) {
-        self.state = State::Primitive;
-        self.value = String::from(val);
-        self.pos = pos;
+        return Some(Token::Operation { span, val: operation.unwrap().clone() });
     }
 
-    fn init_operation(&mut self, val: char, pos: usize) {
-        self.state = State::Operation;
-        self.value = String::from(val);
-        self.pos = pos;
-    }
+    /// Unknown token at position. When it's inside of a text, which was read back, the whole text is reported.
+    fn unknown(&self, chars: &[char], pos: usize, length: usize) -> Token<T> {
+        let (start, length) = match self.attempt {
+            Some((start, reach)) if start + reach > pos => (start, reach),
+            _ => (pos, length),
+        };
 
-    fn add_symbol(&mut self, symbol: char) {
-        self.value.push(symbol)
+        return Token::Unknown { span: self.span(start, length), val: chars[start..start + length].iter().collect() };
     }
 
-    fn to_whitespace_token(&self) -> Token<T> {
-        return Token::WhiteSpace { pos: self.pos, val: self.value.clone() };
-    }
+    /// Length in chars of comment at position. Unterminated block comment is an error.
+    fn comment_at(&self, chars: &[char], pos: usize, expr_calculator: &ExpressionDeclarator<T>) -> Result<Option<usize>, Token<T>> {
+        for (start, end) in expr_calculator.comments.iter() {
+            if !starts_with(&chars[pos..], start) {
+                continue;
+            }
+            let from = pos + start.chars().count();
+            let length = match end {
+                None => chars[from..].iter().position(|it| *it == '\n').unwrap_or(chars.len() - from),
+                Some(end) => match (from..chars.len()).find(|it| starts_with(&chars[*it..], end)) {
+                    Some(found) => found - from + end.chars().count(),
+                    None => return Result::Err(Token::Unknown { span: self.span(pos, start.chars().count()), val: start.clone() }),
+                },
+            };
+
+            return Result::Ok(Some(from - pos + length));
+        }
 
-    fn to_primitive(&self, expr_calculator: &ExpressionDeclarator<T>) -> Result<Token<T>, Token<T>> {
-        let val = expr_calculator.handler.from_string(&self.value);
+        return Result::Ok(None);
+    }
 
-        if val.is_err() {
-            return Result::Err(Token::Unknown { pos: self.pos, val: self.value.clone() });
-        }
+    fn add_token(&mut self, token: Token<T>) -> Result<(), Token<T>> {
+        self.out.push(token);
 
-        return Result::Ok(Token::Primitive { pos: self.pos, val: val.unwrap(), original: self.value.clone() });
+        return Result::Ok(());
     }
 
-    fn is_empty(&self) -> bool {
-        return self.value.is_empty();
+    /// The last token, which is a part of expression.
+    /// Only ignored tokens after it are passed, so tokenization stays linear.
+    fn take_last(&self) -> Option<&Token<T>> {
+        return self.out.iter().rev().find(|it| !it.is_ignored());
     }
 
-    fn suitable_for_prefix(&self, value: &String, expr_calculator: &ExpressionDeclarator<T>) -> bool {
+    fn suitable_for_prefix(&self, value: &str, expr_calculator: &ExpressionDeclarator<T>) -> bool {
         if expr_calculator.make_by_type(value, OperationType::Prefix).is_none() {
             return false;
         }
@@ -413,55 +884,36 @@ impl<T: 'static + Clone> Context<T> {
             return true;
         }
 
-        return match last.unwrap() {
-            Token::Open { .. } => true,
-            Token::Operation { pos: _pos, val } => match val.op_type {
-                OperationType::Constant => false,
-                _ => true
-            }
-            _ => false
-        };
+        let last = last.unwrap();
+        if last.ends_operand() {
+            // Operand before prefix operation is allowed only for implicit operation, e.g. `2sin(x)`,
+            // and only if the same signature can't be read as an operation with left operand
+            return expr_calculator.implicit.is_some()
+                && expr_calculator.make_by_type(value, OperationType::Postfix).is_none()
+                && expr_calculator.make_by_type(value, OperationType::Infix).is_none()
+                && expr_calculator.make_by_type(value, OperationType::Ternary).is_none();
+        }
+
+        return matches!(last, Token::Open { .. } | Token::Separator { .. } | Token::Operation { .. });
     }
 
-    fn suitable_for_postfix(&self, value: &String, expr_calculator: &ExpressionDeclarator<T>) -> bool {
+    fn suitable_for_postfix(&self, value: &str, expr_calculator: &ExpressionDeclarator<T>) -> bool {
         if expr_calculator.make_by_type(value, OperationType::Postfix).is_none() {
             return false;
         }
 
-        let last = self.take_last();
-        if last.is_none() {
-            return false;
-        }
-
-        return match last.unwrap() {
-            Token::Close { .. } => true,
-            Token::Primitive { .. } => true,
-            Token::Operation { pos: _pos, val } => match val.op_type {
-                OperationType::Constant | OperationType::Postfix => true,
-                _ => false
-            }
-            _ => false
-        };
+        return self.take_last().is_some_and(|it| it.ends_operand());
     }
 
-    fn suitable_for_infix(&self, value: &String, expr_calculator: &ExpressionDeclarator<T>) -> bool {
-        if expr_calculator.make_by_type(value, OperationType::Infix).is_none() {
+    fn suitable_for_infix(&self, value: &str, expr_calculator: &ExpressionDeclarator<T>, op_type: OperationType) -> bool {
+        if expr_calculator.make_by_type(value, op_type).is_none() {
             return false;
         }
 
-        let last = self.take_last();
-        if last.is_none() {
-            return false;
-        }
-
-        return match last.unwrap() {
-            Token::Close { .. } => true,
-            Token::Primitive { .. } => true,
-            Token::Operation { pos: _pos, val } => match val.op_type {
-                OperationType::Constant | OperationType::Postfix => true,
-                _ => false
-            }
-            _ => false
-        };
+        return self.take_last().is_some_and(|it| it.ends_operand());
     }
 }
+
+fn starts_with(chars: &[char], prefix: &str) -> bool {
+    return prefix.chars().count() <= chars.len() && prefix.chars().zip(chars.iter()).all(|(expected, char)| expected == *char);
+}
@@ -0,0 +1,202 @@
+/// Notation of formatted numbers
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Notation {
+    /// Positional notation, e.g. `12345.6`
+    Fixed,
+    /// One digit before the decimal point and exponent, e.g. `1.23456e4`
+    Scientific,
+    /// One to three digits before the decimal point and exponent multiple of three, e.g. `12.3456e3`
+    Engineering,
+}
+
+/// Precision of formatted numbers
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Precision {
+    /// Fixed number of digits after the decimal point, trailing zeros are kept
+    Decimals(usize),
+    /// Number of significant digits, trailing zeros are removed
+    Significant(usize),
+}
+
+/// Formatter of `f64` results.
+/// Default is fixed notation with 15 significant digits, so float noise is hidden: `0.1+0.2` is printed as `0.3`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct F64Formatter {
+    notation: Notation,
+    precision: Precision,
+    decimal_separator: char,
+    thousands_separator: Option<char>,
+    nan: String,
+    infinity: String,
+    negative_infinity: String,
+}
+
+impl F64Formatter {
+    pub fn new() -> F64Formatter {
+        return F64Formatter {
+            notation: Notation::Fixed,
+            precision: Precision::Significant(15),
+            decimal_separator: '.',
+            thousands_separator: None,
+            nan: "NaN".to_string(),
+            infinity: "inf".to_string(),
+            negative_infinity: "-inf".to_string(),
+        };
+    }
+
+    pub fn with_notation(mut self, notation: Notation) -> F64Formatter {
+        self.notation = notation;
+
+        return self;
+    }
+
+    /// Fixed number of digits after the decimal point, e.g. `1.50` for 2
+    pub fn with_decimals(mut self, decimals: usize) -> F64Formatter {
+        self.precision = Precision::Decimals(decimals);
+
+        return self;
+    }
+
+    /// Round to significant digits, e.g. `1.23` for `1.23456` and 3
+    pub fn with_significant_digits(mut self, digits: usize) -> F64Formatter {
+        self.precision = Precision::Significant(digits.max(1));
+
+        return self;
+    }
+
+    pub fn with_decimal_separator(mut self, separator: char) -> F64Formatter {
+        self.decimal_separator = separator;
+
+        return self;
+    }
+
+    /// Separate groups of three digits of the integer part, e.g. `1,234,567` for ','
+    pub fn with_thousands_separator(mut self, separator: char) -> F64Formatter {
+        self.thousands_separator = Some(separator);
+
+        return self;
+    }
+
+    pub fn with_nan(mut self, nan: &str) -> F64Formatter {
+        self.nan = nan.to_string();
+
+        return self;
+    }
+
+    pub fn with_infinity(mut self, infinity: &str, negative_infinity: &str) -> F64Formatter {
+        self.infinity = infinity.to_string();
+        self.negative_infinity = negative_infinity.to_string();
+
+        return self;
+    }
+
+    pub fn format(&self, value: f64) -> String {
+        if value.is_nan() {
+            return self.nan.clone();
+        }
+        if value.is_infinite() {
+            return if value > 0.0 { self.infinity.clone() } else { self.negative_infinity.clone() };
+        }
+
+        let (integer, fraction, exponent) = match self.notation {
+            Notation::Fixed => self.fixed(value.abs()),
+            Notation::Scientific => self.exponential(value.abs(), 1),
+            Notation::Engineering => self.exponential(value.abs(), 3),
+        };
+
+        let zero = integer.chars().chain(fraction.chars()).all(|it| it == '0');
+        let mut result = if value.is_sign_negative() && !zero { "-".to_string() } else { String::new() };
+        result.push_str(&self.group(&integer));
+        if !fraction.is_empty() {
+            result.push(self.decimal_separator);
+            result.push_str(&fraction);
+        }
+        if let Some(exponent) = exponent {
+            result.push_str(&format!("e{}", exponent));
+        }
+
+        return result;
+    }
+
+    /// Integer and fractional digits of positional notation
+    fn fixed(&self, value: f64) -> (String, String, Option<i32>) {
+        return match self.precision {
+            Precision::Decimals(decimals) => {
+                let text = format!("{:.*}", decimals, value);
+                let (integer, fraction) = text.split_once('.').unwrap_or((&text, ""));
+                (integer.to_string(), fraction.to_string(), None)
+            }
+            Precision::Significant(count) => {
+                let (digits, exponent) = significant(value, count);
+                let (integer, fraction) = if exponent >= 0 {
+                    let point = exponent as usize + 1;
+                    let padded = format!("{:0<width$}", digits, width = point);
+                    (padded[..point].to_string(), padded[point..].to_string())
+                } else {
+                    ("0".to_string(), format!("{}{}", "0".repeat((-exponent - 1) as usize), digits))
+                };
+                (integer, fraction.trim_end_matches('0').to_string(), None)
+            }
+        };
+    }
+
+    /// Mantissa digits and exponent, which is multiple of step
+    fn exponential(&self, value: f64, step: i32) -> (String, String, Option<i32>) {
+        let (digits, exponent) = match self.precision {
+            Precision::Significant(count) => significant(value, count),
+            Precision::Decimals(decimals) => {
+                let shift = exponent_of(value).rem_euclid(step) as usize;
+                significant(value, decimals + 1 + shift)
+            }
+        };
+
+        // Rounding may carry into a new leading digit, then digits are "10..0" and have to be padded or cut
+        let point = exponent.rem_euclid(step) as usize + 1;
+        let fraction = match self.precision {
+            Precision::Significant(_) => digits.get(point..).unwrap_or("").trim_end_matches('0').to_string(),
+            Precision::Decimals(decimals) => format!("{:0<width$}", digits.get(point..).unwrap_or(""), width = decimals)[..decimals].to_string(),
+        };
+        let padded = format!("{:0<width$}", digits, width = point);
+
+        return (padded[..point].to_string(), fraction, Some(exponent - exponent.rem_euclid(step)));
+    }
+
+    fn group(&self, integer: &str) -> String {
+        let separator = match self.thousands_separator {
+            Some(separator) => separator,
+            None => return integer.to_string(),
+        };
+
+        let mut result = String::new();
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i) % 3 == 0 {
+                result.push(separator);
+            }
+            result.push(digit);
+        }
+
+        return result;
+    }
+}
+
+impl Default for F64Formatter {
+    fn default() -> Self {
+        return F64Formatter::new();
+    }
+}
+
+/// Decimal digits of value rounded to count significant digits and exponent of the first digit.
+/// Zero has zero exponent.
+fn significant(value: f64, count: usize) -> (String, i32) {
+    let text = format!("{:.*e}", count.max(1) - 1, value);
+    let (mantissa, exponent) = text.split_once('e').unwrap();
+
+    return (mantissa.replace('.', ""), exponent.parse().unwrap());
+}
+
+/// Exponent of the first digit of value without rounding
+fn exponent_of(value: f64) -> i32 {
+    let text = format!("{:e}", value);
+
+    return text.split_once('e').unwrap().1.parse().unwrap();
+}
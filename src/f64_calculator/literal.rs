@@ -0,0 +1,233 @@
+//! Grammar of f64 literals. `.` and `,` stand for decimal and group separators of locale.
+//!
+//! ```text
+//! literal     = decimal | hexadecimal | special
+//! decimal     = mantissa [ ( "e" | "E" ) exponent ]
+//! mantissa    = integer [ "." [ digits ] ] | "." digits
+//! integer     = digits { "," digits }
+//! digits      = digit { [ "_" ] digit }
+//! hexadecimal = "0" ( "x" | "X" ) hex_mantissa [ ( "p" | "P" ) exponent ]
+//! hex_mantissa = hex_digits [ "." [ hex_digits ] ] | "." hex_digits
+//! hex_digits  = hex_digit { [ "_" ] hex_digit }
+//! exponent    = [ "+" | "-" ] digits
+//! special     = "inf" | "infinity" | "nan"
+//! ```
+//!
+//! Special literals are case insensitive. Groups of integer part must follow grouping of locale,
+//! hexadecimal exponent is a power of two.
+
+use crate::f64_calculator::locale::{Groups, Locale};
+
+const SPECIAL: [&str; 3] = ["inf", "infinity", "nan"];
+
+/// States of prefix automaton
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    Start,
+    /// "0x", a digit or decimal separator must follow
+    HexPrefix,
+    Integer,
+    /// Digit or group separator in integer part, a digit must follow
+    IntegerSeparator,
+    /// Decimal separator without integer digits, a digit must follow
+    LeadingPoint,
+    /// Decimal separator after integer digits
+    Point,
+    Fraction,
+    FractionSeparator,
+    /// Exponent mark, a sign or digit must follow
+    ExponentMark,
+    ExponentSign,
+    Exponent,
+    ExponentSeparator,
+}
+
+impl State {
+    fn is_final(self) -> bool {
+        return matches!(self, State::Integer | State::Point | State::Fraction | State::Exponent);
+    }
+
+    fn in_integer(self) -> bool {
+        return matches!(self, State::Start | State::HexPrefix | State::Integer | State::IntegerSeparator);
+    }
+}
+
+/// Literal or its beginning
+pub(crate) struct Literal {
+    /// Digits with '.' as decimal separator and exponent, without digit and group separators and hexadecimal prefix
+    text: String,
+    hex: bool,
+    /// Whether literal is finished, otherwise it's only a beginning of one
+    pub(crate) complete: bool,
+}
+
+impl Literal {
+    pub(crate) fn value(&self) -> Option<f64> {
+        if !self.complete {
+            return None;
+        }
+        if self.hex {
+            return hex_value(&self.text);
+        }
+
+        return self.text.parse::<f64>().ok();
+    }
+}
+
+/// Scan input by prefix automaton. None means, that input is not a literal and can't be continued to one.
+pub(crate) fn scan(input: &str, locale: &Locale) -> Option<Literal> {
+    let lowercase = input.to_lowercase();
+    if SPECIAL.iter().any(|it| it.starts_with(&lowercase)) {
+        let complete = SPECIAL.contains(&lowercase.as_str());
+        return Some(Literal { text: lowercase, hex: false, complete });
+    }
+
+    let mut scanner = Scanner { locale, state: State::Start, hex: false, text: String::new(), groups: Groups::new() };
+    for char in input.chars() {
+        if !scanner.step(char) {
+            return None;
+        }
+    }
+
+    let state = scanner.state;
+    if !locale.valid_groups(&scanner.groups, !state.in_integer()) {
+        return None;
+    }
+
+    return Some(Literal { text: scanner.text, hex: scanner.hex, complete: state.is_final() && locale.valid_groups(&scanner.groups, true) });
+}
+
+/// The longest complete literal at the start of input with its length in chars, and the length of the longest text,
+/// which can start a literal. Same as `scan` of every prefix, but digits are scanned once and value is parsed
+/// only for the longest complete prefix, so it's linear in the length of literal.
+pub(crate) fn longest(input: &[char], locale: &Locale) -> (Option<(usize, f64)>, usize) {
+    let longest_special = SPECIAL.iter().map(|it| it.len()).max().unwrap();
+    if input.first().is_some_and(|char| SPECIAL.iter().any(|it| it.starts_with(char.to_ascii_lowercase()))) {
+        let mut found = None;
+        let mut reach = 0;
+        for length in 1..=input.len().min(longest_special) {
+            let literal = match scan(&input[..length].iter().collect::<String>(), locale) {
+                Some(literal) => literal,
+                None => break,
+            };
+            reach = length;
+            if let Some(value) = literal.value() {
+                found = Some((length, value));
+            }
+        }
+        return (found, reach);
+    }
+
+    let mut scanner = Scanner { locale, state: State::Start, hex: false, text: String::new(), groups: Groups::new() };
+    let mut complete: Vec<usize> = Vec::new();
+    let mut reach = 0;
+    for (index, char) in input.iter().enumerate() {
+        if !scanner.step(*char) || !locale.valid_groups(&scanner.groups, !scanner.state.in_integer()) {
+            break;
+        }
+        reach = index + 1;
+        if scanner.state.is_final() && locale.valid_groups(&scanner.groups, true) {
+            complete.push(reach);
+        }
+    }
+
+    // Complete literal has a value, so only the longest one is parsed in practice
+    for length in complete.into_iter().rev() {
+        if let Some(value) = scan(&input[..length].iter().collect::<String>(), locale).and_then(|literal| literal.value()) {
+            return (Some((length, value)), reach);
+        }
+    }
+
+    return (None, reach);
+}
+
+struct Scanner<'a> {
+    locale: &'a Locale,
+    state: State,
+    hex: bool,
+    text: String,
+    /// Sizes of digit groups of decimal integer part
+    groups: Groups,
+}
+
+impl<'a> Scanner<'a> {
+    /// Move to the next state by char, false if there is no transition
+    fn step(&mut self, char: char) -> bool {
+        let radix = if self.hex { 16 } else { 10 };
+        let exponent_mark = if self.hex { ['p', 'P'] } else { ['e', 'E'] };
+
+        self.state = match self.state {
+            State::Start | State::HexPrefix | State::Integer | State::IntegerSeparator if char.is_digit(radix) => {
+                self.groups.digit();
+                self.text.push(char);
+                State::Integer
+            }
+            State::Integer if (char == 'x' || char == 'X') && self.text == "0" && !self.hex => {
+                self.hex = true;
+                self.text.clear();
+                self.groups = Groups::new();
+                State::HexPrefix
+            }
+            State::Integer if char == '_' => State::IntegerSeparator,
+            State::Integer if Some(char) == self.locale.group_separator && !self.hex => {
+                self.groups.separator(self.locale.grouping);
+                State::IntegerSeparator
+            }
+            State::Start | State::HexPrefix if char == self.locale.decimal_separator => {
+                self.text.push('.');
+                State::LeadingPoint
+            }
+            State::Integer if char == self.locale.decimal_separator => {
+                self.text.push('.');
+                State::Point
+            }
+            State::LeadingPoint | State::Point | State::Fraction | State::FractionSeparator if char.is_digit(radix) => {
+                self.text.push(char);
+                State::Fraction
+            }
+            State::Fraction if char == '_' => State::FractionSeparator,
+            State::Integer | State::Point | State::Fraction if exponent_mark.contains(&char) => {
+                self.text.push(if self.hex { 'p' } else { 'e' });
+                State::ExponentMark
+            }
+            State::ExponentMark if char == '+' || char == '-' => {
+                self.text.push(char);
+                State::ExponentSign
+            }
+            State::ExponentMark | State::ExponentSign | State::Exponent | State::ExponentSeparator if char.is_ascii_digit() => {
+                self.text.push(char);
+                State::Exponent
+            }
+            State::Exponent if char == '_' => State::ExponentSeparator,
+            _ => return false,
+        };
+
+        return true;
+    }
+}
+
+/// Value of hexadecimal mantissa with optional binary exponent, e.g. "1.8p3".
+/// Too large exponent gives infinity or zero, as for decimal literals.
+fn hex_value(text: &str) -> Option<f64> {
+    let (mantissa, exponent) = match text.split_once('p') {
+        Some((mantissa, exponent)) if exponent.starts_with('-') => (mantissa, exponent.parse::<i32>().unwrap_or(i32::MIN)),
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<i32>().unwrap_or(i32::MAX)),
+        None => (text, 0),
+    };
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+
+    let mut value = 0.0;
+    for digit in integer.chars() {
+        value = value * 16.0 + digit.to_digit(16)? as f64;
+    }
+    let mut scale = 1.0 / 16.0;
+    for digit in fraction.chars() {
+        value += digit.to_digit(16)? as f64 * scale;
+        scale /= 16.0;
+    }
+    if value == 0.0 {
+        return Some(0.0);
+    }
+
+    return Some(value * 2.0_f64.powi(exponent));
+}
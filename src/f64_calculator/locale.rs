@@ -0,0 +1,116 @@
+/// Sizes of digit groups of the integer part
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Grouping {
+    /// Groups of three digits, e.g. `1,234,567`
+    Thousands,
+    /// The last group of three digits and groups of two before it, e.g. `12,34,567`
+    Indian,
+}
+
+impl Grouping {
+    /// Sizes of groups from the right, the last size repeats
+    fn sizes(&self) -> &'static [usize] {
+        return match self {
+            Grouping::Thousands => &[3],
+            Grouping::Indian => &[3, 2],
+        };
+    }
+
+    fn size(&self, from_right: usize) -> usize {
+        let sizes = self.sizes();
+
+        return sizes[from_right.min(sizes.len() - 1)];
+    }
+}
+
+/// Separators of numeric literals.
+/// Group separator is optional in literals, but when it's used, groups must follow the grouping rule.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Locale {
+    pub decimal_separator: char,
+    pub group_separator: Option<char>,
+    pub grouping: Grouping,
+}
+
+impl Locale {
+    pub fn new(decimal_separator: char, group_separator: Option<char>) -> Locale {
+        return Locale { decimal_separator, group_separator, grouping: Grouping::Thousands };
+    }
+
+    pub fn with_grouping(mut self, grouping: Grouping) -> Locale {
+        self.grouping = grouping;
+
+        return self;
+    }
+
+    /// `3.14`, `1,234.5`
+    pub fn english() -> Locale {
+        return Locale::new('.', Some(','));
+    }
+
+    /// `3,14`, `1.234,5`
+    pub fn european() -> Locale {
+        return Locale::new(',', Some('.'));
+    }
+
+    /// Whether the default argument separator ',' is taken by literals
+    pub fn uses_comma(&self) -> bool {
+        return self.decimal_separator == ',' || self.group_separator == Some(',');
+    }
+
+    /// Check sizes of groups. Unfinished integer part may be continued with digits of the last group and with more groups.
+    /// Groups before the last one have the same size at any position, so they are checked in both cases.
+    pub(crate) fn valid_groups(&self, groups: &Groups, finished: bool) -> bool {
+        if groups.count == 1 {
+            return true;
+        }
+        if !groups.inner_valid {
+            return false;
+        }
+
+        let largest = *self.grouping.sizes().iter().max().unwrap();
+        if finished {
+            return groups.last == self.grouping.size(0) && groups.first >= 1 && groups.first <= self.grouping.size(groups.count - 1);
+        }
+
+        return groups.last <= largest && groups.first >= 1 && groups.first <= largest;
+    }
+}
+
+/// Sizes of digit groups of the integer part, collected digit by digit, so they are checked in constant time
+#[derive(Clone, Debug)]
+pub(crate) struct Groups {
+    first: usize,
+    last: usize,
+    count: usize,
+    /// Groups between the first and the last ones have the size of grouping
+    inner_valid: bool,
+}
+
+impl Groups {
+    pub(crate) fn new() -> Groups {
+        return Groups { first: 0, last: 0, count: 1, inner_valid: true };
+    }
+
+    pub(crate) fn digit(&mut self) {
+        if self.count == 1 {
+            self.first += 1;
+        }
+        self.last += 1;
+    }
+
+    pub(crate) fn separator(&mut self, grouping: Grouping) {
+        if self.count > 1 && self.last != grouping.size(1) {
+            self.inner_valid = false;
+        }
+        self.count += 1;
+        self.last = 0;
+    }
+}
+
+impl Default for Locale {
+    /// '.' as decimal separator without groups
+    fn default() -> Self {
+        return Locale::new('.', None);
+    }
+}
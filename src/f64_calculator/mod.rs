@@ -1,108 +1,273 @@
-use crate::declarator::{ExpressionDeclarator, HIGHEST_ORDER, LOWEST_ORDER, LOW_ORDER, MEDIUM_ORDER, HIGH_ORDER, PrimitiveHandler};
-use regex::Regex;
-use std::str::FromStr;
-use std::f64::consts::{PI, E};
-use lazy_static::lazy_static;
+pub mod format;
+pub mod locale;
+mod literal;
+mod vectorized;
 
-struct F64Handler {}
+use crate::calculator::Executor;
+use crate::declarator::{ExpressionDeclarator, HIGHEST_ORDER, LOWEST_ORDER, LOW_ORDER, MEDIUM_ORDER, HIGH_ORDER, ULTIMATE_ORDER, PrimitiveHandler};
+use crate::f64_calculator::literal::scan;
+use crate::f64_calculator::locale::Locale;
+use crate::operation::{Associativity, Operation, OperationType};
+use std::f64::consts::{PI, E, TAU};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// Priority of postfix operations, e.g. factorial. They bind tighter than prefix functions: `-3! = -(3!)`
+pub const POSTFIX_ORDER: u8 = HIGHEST_ORDER + 10;
+
+/// Signature, description and kernel of built-in operations. Scalar and vectorized executors are built from the kernel.
+type UnaryFunction = (&'static str, &'static str, fn(f64) -> f64);
+type BinaryFunction = (&'static str, &'static str, fn(f64, f64) -> f64);
+/// Binary kernel with priority of infix operator
+type Operator = (&'static str, &'static str, fn(f64, f64) -> f64, u8);
+
+const OPERATORS: [Operator; 4] = [
+    ("+", "Addition", f64::add, LOWEST_ORDER),
+    ("-", "Subtraction", f64::sub, LOWEST_ORDER),
+    ("*", "Multiplication", f64::mul, LOW_ORDER),
+    ("/", "Division", f64::div, LOW_ORDER),
+];
+
+const ELEMENTARY: [UnaryFunction; 5] = [
+    ("sqrt", "Square root", f64::sqrt),
+    ("ln", "Natural logarithm", f64::ln),
+    ("log10", "Common logarithm", f64::log10),
+    ("log2", "Binary logarithm", f64::log2),
+    ("exp", "Exponent", f64::exp),
+];
+
+const TRIGONOMETRIC: [UnaryFunction; 3] = [
+    ("sin", "Sine", f64::sin),
+    ("cos", "Cosine", f64::cos),
+    ("tan", "Tangent", f64::tan),
+];
+
+const INVERSE_TRIGONOMETRIC: [UnaryFunction; 3] = [
+    ("asin", "Arcsine", f64::asin),
+    ("acos", "Arccosine", f64::acos),
+    ("atan", "Arctangent", f64::atan),
+];
+
+const FUNCTIONS: [UnaryFunction; 13] = [
+    ("sinh", "Hyperbolic sine", f64::sinh),
+    ("cosh", "Hyperbolic cosine", f64::cosh),
+    ("tanh", "Hyperbolic tangent", f64::tanh),
+    ("asinh", "Inverse hyperbolic sine", f64::asinh),
+    ("acosh", "Inverse hyperbolic cosine", f64::acosh),
+    ("atanh", "Inverse hyperbolic tangent", f64::atanh),
+    ("abs", "Absolute value", f64::abs),
+    ("floor", "Largest integer less than or equal to x", f64::floor),
+    ("ceil", "Smallest integer greater than or equal to x", f64::ceil),
+    ("round", "Nearest integer, half away from zero", f64::round),
+    ("trunc", "Integer part", f64::trunc),
+    ("sign", "Sign, -1, 0 or 1", sign),
+    ("cbrt", "Cube root", f64::cbrt),
+];
+
+const BINARY_FUNCTIONS: [BinaryFunction; 4] = [
+    ("log", "Logarithm, log(base, x)", |base, x| x.log(base)),
+    ("min", "Minimum, min(x, y)", f64::min),
+    ("max", "Maximum, max(x, y)", f64::max),
+    ("hypot", "Hypotenuse, hypot(x, y)", f64::hypot),
+];
+
+/// Postfix units of angles with conversion to radians
+const ANGLE_UNITS: [UnaryFunction; 2] = [
+    ("°", "degrees", f64::to_radians),
+    ("rad", "radians", radians),
+];
+
+/// Unit of angles for trigonometric functions and their inverses
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AngleMode {
+    Radians,
+    Degrees,
+}
+
+impl AngleMode {
+    pub fn unit(&self) -> &'static str {
+        return match self {
+            AngleMode::Radians => "radians",
+            AngleMode::Degrees => "degrees",
+        };
+    }
+
+    /// Angle of the mode unit in radians
+    fn to_radians(self, angle: f64) -> f64 {
+        return match self {
+            AngleMode::Radians => angle,
+            AngleMode::Degrees => angle.to_radians(),
+        };
+    }
+
+    /// Angle in radians in the mode unit
+    #[allow(clippy::wrong_self_convention)]
+    fn from_radians(self, angle: f64) -> f64 {
+        return match self {
+            AngleMode::Radians => angle,
+            AngleMode::Degrees => angle.to_degrees(),
+        };
+    }
+}
+
+/// Numeric literals with separators of locale, e.g. `3.14` by default or `3,14` for `Locale::european()`.
+/// See `literal` module for the grammar.
+pub struct F64Handler {
+    locale: Locale,
+}
+
+impl F64Handler {
+    pub fn new() -> F64Handler {
+        return F64Handler::with_locale(Locale::default());
+    }
+
+    pub fn with_locale(locale: Locale) -> F64Handler {
+        return F64Handler { locale };
+    }
+}
+
+impl Default for F64Handler {
+    fn default() -> Self {
+        return F64Handler::new();
+    }
+}
 
 impl PrimitiveHandler<f64> for F64Handler {
     fn from_string(&self, input: &String) -> Result<f64, ()> {
-        let result = f64::from_str(input);
-        if result.is_err() {
-            return Result::Err(())
-        }
-
-        return Result::Ok(result.ok().unwrap());
+        return match scan(input, &self.locale).and_then(|literal| literal.value()) {
+            Some(value) => Result::Ok(value),
+            None => Result::Err(()),
+        };
     }
 
     fn can_start_with(&self, input: String) -> bool {
-        return PRIMITIVE_INCOMPLETE_1.is_match(&input) || PRIMITIVE_INCOMPLETE_2.is_match(&input);
+        return scan(&input, &self.locale).is_some();
+    }
+
+    fn longest(&self, input: &[char]) -> (Option<(usize, f64)>, usize) {
+        return literal::longest(input, &self.locale);
+    }
+}
+
+/// Unicode signatures and ASCII ones they stand for
+pub const UNICODE_ALIASES: [(&str, &str); 9] = [
+    ("×", "*"),
+    ("÷", "/"),
+    ("−", "-"),
+    ("√", "sqrt"),
+    ("π", "pi"),
+    ("τ", "tau"),
+    ("≤", "<="),
+    ("≥", ">="),
+    ("≠", "!="),
+];
+
+/// Opt-in Unicode aliases of operations, which are declared by calculator, e.g. `2 × π` or `√4 ≠ 3` for `mixed_calculator`
+pub fn add_unicode_aliases<T: 'static + Clone>(calculator: &mut ExpressionDeclarator<T>) {
+    for (alias, signature) in UNICODE_ALIASES.iter() {
+        calculator.add_alias(alias.to_string(), signature.to_string());
     }
 }
 
+/// Calculator with angles in radians
 pub fn f64_calculator() -> ExpressionDeclarator<f64> {
-    let mut calculator = ExpressionDeclarator::<f64>::new(Box::new(F64Handler {}));
+    return f64_calculator_with(AngleMode::Radians);
+}
 
-    calculator.add_prefix(
-        "-".to_string(),
-        "Negation".to_string(),
-        Box::new(|op1| { -op1.clone() }),
-        HIGH_ORDER
-    );
+/// Calculator with angles of trigonometric functions in the unit of mode.
+/// Postfix `°` and `rad` convert angle to the unit of mode, so `sin(90°)` and `sin((pi/2) rad)` are 1 in any mode.
+/// Postfix operations bind tighter than `/`, so `sin(pi/2 rad)` is `sin(pi/(2 rad))`.
+/// ```
+/// use expression_declarator::f64_calculator::{f64_calculator_with, AngleMode};
+///
+/// for mode in [AngleMode::Radians, AngleMode::Degrees] {
+///     let calculator = f64_calculator_with(mode);
+///     assert_eq!(calculator.calculate("sin(90°)").ok().unwrap(), 1.0);
+///     assert_eq!(calculator.calculate("sin((pi/2) rad)").ok().unwrap(), 1.0);
+/// }
+/// ```
+pub fn f64_calculator_with(mode: AngleMode) -> ExpressionDeclarator<f64> {
+    return f64_calculator_with_locale(Locale::default(), mode);
+}
 
-    calculator.add_infix(
-        "+".to_string(),
-        "Addition".to_string(),
-        Box::new(|op1, op2| { op1 + op2 }),
-        LOWEST_ORDER,
-    );
-    calculator.add_infix(
-        "-".to_string(),
-        "Subtraction".to_string(),
-        Box::new(|op1, op2| { op1 - op2 }),
-        LOWEST_ORDER,
-    );
-    calculator.add_infix(
-        "*".to_string(),
-        "Multiplication".to_string(),
-        Box::new(|op1, op2| { op1 * op2 }),
-        LOW_ORDER,
-    );
-    calculator.add_infix(
-        "/".to_string(),
-        "Division".to_string(),
-        Box::new(|op1, op2| { op1 / op2 }),
+/// Calculator with numeric literals of locale.
+/// Arguments of functions are separated by ';' when ',' is used in literals, e.g. `max(1,5; 2,5)`.
+pub fn f64_calculator_with_locale(locale: Locale, mode: AngleMode) -> ExpressionDeclarator<f64> {
+    let separator = if locale.uses_comma() { ';' } else { ',' };
+    let mut calculator = ExpressionDeclarator::<f64>::new(Box::new(F64Handler::with_locale(locale)));
+    calculator.set_separator(separator);
+    calculator.add_line_comment("#".to_string());
+
+    calculator.add_operation(unary_operation("-", "Negation".to_string(), OperationType::Prefix, f64::neg, HIGH_ORDER));
+    for (signature, description, kernel, order) in OPERATORS.iter() {
+        calculator.add_operation(binary_operation(signature, description.to_string(), OperationType::Infix, *kernel, *order));
+    }
+    let mut power = binary_operation("^", "Product".to_string(), OperationType::Infix, f64::powf, MEDIUM_ORDER);
+    power.associativity = Associativity::Right;
+    calculator.add_operation(power);
+
+    for (signature, description, kernel) in ELEMENTARY.iter() {
+        calculator.add_operation(unary_operation(signature, description.to_string(), OperationType::Prefix, *kernel, HIGHEST_ORDER));
+    }
+    for (signature, description, function) in TRIGONOMETRIC.iter() {
+        let function = *function;
+        calculator.add_operation(unary_operation(
+            signature,
+            format!("{}, angle in {}", description, mode.unit()),
+            OperationType::Prefix,
+            move |op1| { function(mode.to_radians(op1)) },
+            HIGHEST_ORDER,
+        ));
+    }
+    for (signature, description, function) in INVERSE_TRIGONOMETRIC.iter() {
+        let function = *function;
+        calculator.add_operation(unary_operation(
+            signature,
+            format!("{}, angle in {}", description, mode.unit()),
+            OperationType::Prefix,
+            move |op1| { mode.from_radians(function(op1)) },
+            HIGHEST_ORDER,
+        ));
+    }
+    for (signature, description, kernel) in FUNCTIONS.iter() {
+        calculator.add_operation(unary_operation(signature, description.to_string(), OperationType::Prefix, *kernel, HIGHEST_ORDER));
+    }
+
+    for (signature, unit, to_radians) in ANGLE_UNITS.iter() {
+        let to_radians = *to_radians;
+        calculator.add_operation(unary_operation(
+            signature,
+            format!("Angle in {} to {}", unit, mode.unit()),
+            OperationType::Postfix,
+            move |op1| { mode.from_radians(to_radians(op1)) },
+            POSTFIX_ORDER,
+        ));
+    }
+    calculator.add_operation(unary_operation(
+        "!",
+        "Factorial, gamma(x+1) for non-integers".to_string(),
+        OperationType::Postfix,
+        factorial,
+        POSTFIX_ORDER,
+    ));
+    calculator.add_operation(binary_operation(
+        "%",
+        "Modulo, result has the sign of divisor".to_string(),
+        OperationType::Infix,
+        modulo,
         LOW_ORDER,
-    );
-    calculator.add_infix(
-        "^".to_string(),
-        "Product".to_string(),
-        Box::new(|op1, op2| { op1.powf(op2) }),
-        MEDIUM_ORDER,
-    );
-    calculator.add_prefix(
-        "sqrt".to_string(),
-        "Square root".to_string(),
-        Box::new(|op1| { op1.sqrt() }),
-        HIGHEST_ORDER
-    );
-    calculator.add_prefix(
-        "sin".to_string(),
-        "Sine".to_string(),
-        Box::new(|op1| { op1.sin() }),
-        HIGHEST_ORDER
-    );
-    calculator.add_prefix(
-        "cos".to_string(),
-        "Cosine".to_string(),
-        Box::new(|op1| { op1.cos() }),
-        HIGHEST_ORDER
-    );
-    calculator.add_prefix(
-        "ln".to_string(),
-        "Natural logarithm".to_string(),
-        Box::new(|op1| { op1.ln() }),
-        HIGHEST_ORDER
-    );
-    calculator.add_prefix(
-        "log10".to_string(),
-        "Common logarithm".to_string(),
-        Box::new(|op1| { op1.log10() }),
-        HIGHEST_ORDER
-    );
-    calculator.add_prefix(
-        "log2".to_string(),
-        "Binary logarithm".to_string(),
-        Box::new(|op1| { op1.log2() }),
-        HIGHEST_ORDER
-    );
-    calculator.add_prefix(
-        "exp".to_string(),
-        "Exponent".to_string(),
-        Box::new(|op1| { op1.exp() }),
-        HIGHEST_ORDER
-    );
+    ));
+
+    for (signature, description, kernel) in BINARY_FUNCTIONS.iter() {
+        calculator.add_operation(binary_operation(signature, description.to_string(), OperationType::Prefix, *kernel, ULTIMATE_ORDER));
+    }
+    calculator.add_operation(binary_operation(
+        "atan2",
+        format!("Four-quadrant arctangent, atan2(y, x), angle in {}", mode.unit()),
+        OperationType::Prefix,
+        move |y, x| { mode.from_radians(y.atan2(x)) },
+        ULTIMATE_ORDER,
+    ));
+
     calculator.add_constant(
         "pi".to_string(),
         "Constant Pi=3.1415...".to_string(),
@@ -113,11 +278,103 @@ pub fn f64_calculator() -> ExpressionDeclarator<f64> {
         "Constant e=2.7182....".to_string(),
         E,
     );
+    calculator.add_constant(
+        "tau".to_string(),
+        "Constant Tau=2*Pi=6.2831...".to_string(),
+        TAU,
+    );
+    calculator.add_constant(
+        "phi".to_string(),
+        "Golden ratio Phi=1.6180...".to_string(),
+        (1.0 + 5.0_f64.sqrt()) / 2.0,
+    );
 
     return calculator;
 }
 
-lazy_static! {
-    static ref PRIMITIVE_INCOMPLETE_1: Regex = Regex::new(r"^(\d+|\d+\.\d*)$").unwrap();
-    static ref PRIMITIVE_INCOMPLETE_2: Regex = Regex::new(r"^(\d+|\d+\.\d+)[eE][-+]?\d*$").unwrap();
+/// Operation of one operand with scalar and vectorized executors of the same kernel
+fn unary_operation<F>(signature: &str, description: String, op_type: OperationType, kernel: F, order: u8) -> Operation<f64>
+    where F: 'static + Fn(f64) -> f64 + Clone + Send + Sync {
+    let scalar = kernel.clone();
+    let mut operation = Operation::new(
+        signature.to_string(),
+        description,
+        op_type,
+        Executor::Eager(Box::new(move |operands: &[f64]| { scalar(operands[0]) })),
+        1,
+        order,
+    );
+    operation.vectorized = Some(vectorized::unary(kernel));
+
+    return operation;
+}
+
+/// Operation of two operands with scalar and vectorized executors of the same kernel
+fn binary_operation<F>(signature: &str, description: String, op_type: OperationType, kernel: F, order: u8) -> Operation<f64>
+    where F: 'static + Fn(f64, f64) -> f64 + Clone + Send + Sync {
+    let scalar = kernel.clone();
+    let mut operation = Operation::new(
+        signature.to_string(),
+        description,
+        op_type,
+        Executor::Eager(Box::new(move |operands: &[f64]| { scalar(operands[0], operands[1]) })),
+        2,
+        order,
+    );
+    operation.vectorized = Some(vectorized::binary(kernel));
+
+    return operation;
+}
+
+fn modulo(x: f64, y: f64) -> f64 {
+    return x - y * (x / y).floor();
+}
+
+fn radians(x: f64) -> f64 {
+    return x;
+}
+
+fn sign(x: f64) -> f64 {
+    return if x == 0.0 || x.is_nan() { x } else { x.signum() };
+}
+
+/// Exact for non-negative integers up to 170, gamma(x+1) for other values
+fn factorial(x: f64) -> f64 {
+    if x.fract() == 0.0 && (0.0..=170.0).contains(&x) {
+        return (1..=x as u32).fold(1.0, |product, i| product * i as f64);
+    }
+
+    return gamma(x + 1.0);
+}
+
+/// Lanczos approximation of gamma function, g=7, n=9
+fn gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+
+    if x.fract() == 0.0 && x <= 0.0 {
+        return f64::NAN;
+    }
+    if x < 0.5 {
+        return PI / ((PI * x).sin() * gamma(1.0 - x));
+    }
+
+    let x = x - 1.0;
+    let mut sum = COEFFICIENTS[0];
+    for (i, coefficient) in COEFFICIENTS.iter().enumerate().skip(1) {
+        sum += coefficient / (x + i as f64);
+    }
+    let t = x + G + 0.5;
+
+    return (2.0 * PI).sqrt() * t.powf(x + 0.5) * (-t).exp() * sum;
 }
@@ -0,0 +1,21 @@
+//! Executors over columns for batch calculation of built-in operations from their scalar kernels
+
+use crate::calculator::VectorOperationExecutor;
+
+/// Apply function to every value of the only column
+pub(super) fn unary<F: 'static + Fn(f64) -> f64 + Clone + Send + Sync>(function: F) -> Box<dyn VectorOperationExecutor<f64>> {
+    return Box::new(move |args: &[&[f64]], out: &mut [f64]| {
+        for (result, x) in out.iter_mut().zip(args[0]) {
+            *result = function(*x);
+        }
+    });
+}
+
+/// Apply function to every pair of values of two columns
+pub(super) fn binary<F: 'static + Fn(f64, f64) -> f64 + Clone + Send + Sync>(function: F) -> Box<dyn VectorOperationExecutor<f64>> {
+    return Box::new(move |args: &[&[f64]], out: &mut [f64]| {
+        for ((result, x), y) in out.iter_mut().zip(args[0]).zip(args[1]) {
+            *result = function(*x, *y);
+        }
+    });
+}
@@ -0,0 +1,405 @@
+use crate::ast::AstNode;
+use crate::calculator::{BoxedThunk, Executor, Thunk};
+use crate::declarator::{AstBuilder, ExpressionDeclarator};
+use crate::operation::{Operation, ValueType};
+use crate::token::{Span, Token};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Index of node in `FlatAst`
+pub type NodeId = u32;
+
+/// Index of operation in `ExpressionDeclarator::operations()`
+pub type OperationId = u32;
+
+/// Node of `FlatAst`. Texts are byte ranges of the text buffer of the tree, operands are ranges of its operand list.
+#[derive(Clone)]
+pub enum FlatNode<T: Clone> {
+    Primitive { val: T, text: (u32, u32), span: Span },
+    /// Value of constant operation, calculated on building
+    Constant { val: T, op: OperationId, span: Span },
+    Variable { name: (u32, u32), span: Span },
+    Operation { op: OperationId, first_operand: u32, operands: u8, span: Span },
+}
+
+/// AST in flat vectors without a box per node. Nodes are stored in post-order, so operands precede their node.
+/// Operations are referred by index, so the tree is calculated and converted with the declarator, which built it.
+/// After elimination of common subexpressions it's a DAG: equal subtrees are one node, which is an operand of several nodes.
+#[derive(Clone)]
+pub struct FlatAst<T: Clone> {
+    nodes: Vec<FlatNode<T>>,
+    operands: Vec<NodeId>,
+    texts: String,
+    root: NodeId,
+    /// Some nodes are operands of several nodes, so their values are kept during calculation
+    shared: bool,
+}
+
+/// Node without location for search of equal subexpressions. Operations are compared by index, operands by id.
+#[derive(PartialEq, Eq, Hash)]
+enum SharedKey {
+    Primitive(String),
+    Constant(OperationId),
+    Variable(String),
+    Operation(OperationId, Vec<NodeId>),
+}
+
+impl<T: 'static + Clone> ExpressionDeclarator<T> {
+    /// Convert AST to DAG, where equal pure subexpressions are one node, which is calculated once per calculation,
+    /// e.g. `sin(x)` in `sin(x)^2 + sin(x)*cos(x)`. Subexpressions with impure operations are kept apart.
+    /// Operation, which isn't declared in the declarator, is reported as an error.
+    pub fn eliminate_common_subexpressions(&self, ast: &AstNode<T>) -> Result<FlatAst<T>, Token<T>> {
+        return FlatAst::build(ast, FlatAstBuilder::sharing(self));
+    }
+}
+
+impl<T: 'static + Clone> FlatAst<T> {
+    /// Convert AST, which was built by the declarator. Operation of another declarator is reported as an error.
+    pub fn from_ast(ast: &AstNode<T>, declarator: &ExpressionDeclarator<T>) -> Result<FlatAst<T>, Token<T>> {
+        return FlatAst::build(ast, FlatAstBuilder::new(declarator));
+    }
+
+    fn build(ast: &AstNode<T>, mut builder: FlatAstBuilder<T>) -> Result<FlatAst<T>, Token<T>> {
+        let mut frames: Vec<(&AstNode<T>, usize)> = vec![(ast, 0)];
+        let mut ids: Vec<NodeId> = Vec::new();
+
+        while let Some((node, next)) = frames.pop() {
+            let operands: Vec<&AstNode<T>> = match node {
+                AstNode::Unary { p1, .. } => vec![p1],
+                AstNode::Binary { p1, p2, .. } => vec![p1, p2],
+                AstNode::Nary { args, .. } => args.iter().collect(),
+                _ => Vec::new(),
+            };
+            if next < operands.len() {
+                frames.push((node, next + 1));
+                frames.push((operands[next], 0));
+                continue;
+            }
+
+            let id = match node {
+                AstNode::Primitive { val, token: token @ Token::Operation { .. } } => builder.constant(val.clone(), token)?,
+                AstNode::Primitive { val, token } => builder.primitive(val.clone(), token),
+                AstNode::Variable { name, token } => builder.variable(name, token),
+                AstNode::Unary { op, token, .. } | AstNode::Binary { op, token, .. } | AstNode::Nary { op, token, .. } => {
+                    let operands = ids.split_off(ids.len() - operands.len());
+                    builder.operation(op.clone(), operands, token)?
+                }
+            };
+            ids.push(id);
+        }
+
+        return Result::Ok(builder.finish(ids.pop().unwrap()));
+    }
+
+    /// Convert to AST with operations of the declarator, which built this tree. Shared nodes are copied.
+    pub fn to_ast(&self, declarator: &ExpressionDeclarator<T>) -> AstNode<T> {
+        let mut built: Vec<Option<AstNode<T>>> = Vec::with_capacity(self.nodes.len());
+        let mut uses: Vec<usize> = vec![0; self.nodes.len()];
+        for operand in self.operands.iter() {
+            uses[*operand as usize] += 1;
+        }
+        for (id, node) in self.nodes.iter().enumerate() {
+            let token = self.token(id as NodeId, declarator);
+            let ast = match node {
+                FlatNode::Primitive { val, .. } | FlatNode::Constant { val, .. } => AstNode::Primitive { val: val.clone(), token },
+                FlatNode::Variable { name, .. } => AstNode::Variable { name: self.text(*name).to_string(), token },
+                FlatNode::Operation { op, .. } => {
+                    let op = declarator.operations[*op as usize].clone();
+                    let mut args: Vec<AstNode<T>> = self.operands(id as NodeId).iter()
+                        .map(|it| {
+                            uses[*it as usize] -= 1;
+                            match uses[*it as usize] {
+                                0 => built[*it as usize].take().unwrap(),
+                                _ => built[*it as usize].clone().unwrap(),
+                            }
+                        })
+                        .collect();
+                    match args.len() {
+                        1 => AstNode::Unary { op, p1: Box::new(args.pop().unwrap()), token },
+                        2 => {
+                            let p2 = Box::new(args.pop().unwrap());
+                            AstNode::Binary { op, p1: Box::new(args.pop().unwrap()), p2, token }
+                        }
+                        _ => AstNode::Nary { op, args, token },
+                    }
+                }
+            };
+            built.push(Some(ast));
+        }
+
+        return built[self.root as usize].take().unwrap();
+    }
+
+    pub fn root(&self) -> NodeId {
+        return self.root;
+    }
+
+    pub fn node(&self, id: NodeId) -> &FlatNode<T> {
+        return &self.nodes[id as usize];
+    }
+
+    pub fn node_count(&self) -> usize {
+        return self.nodes.len();
+    }
+
+    /// Operands of operation node, empty for leaves
+    pub fn operands(&self, id: NodeId) -> &[NodeId] {
+        return match &self.nodes[id as usize] {
+            FlatNode::Operation { first_operand, operands, .. } => {
+                &self.operands[*first_operand as usize..*first_operand as usize + *operands as usize]
+            }
+            _ => &[],
+        };
+    }
+
+    /// Token, from which node was built
+    pub fn token(&self, id: NodeId, declarator: &ExpressionDeclarator<T>) -> Token<T> {
+        return match &self.nodes[id as usize] {
+            FlatNode::Primitive { val, text, span } => Token::Primitive { span: *span, val: val.clone(), original: self.text(*text).to_string() },
+            FlatNode::Constant { op, span, .. } | FlatNode::Operation { op, span, .. } => {
+                Token::Operation { span: *span, val: declarator.operations[*op as usize].clone() }
+            }
+            FlatNode::Variable { name, span } => Token::Variable { span: *span, val: self.text(*name).to_string() },
+        };
+    }
+
+    /// Names of variables in order of the first appearance
+    pub fn variables(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for node in self.nodes.iter() {
+            if let FlatNode::Variable { name, .. } = node {
+                let name = self.text(*name);
+                if !names.iter().any(|it| it == name) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+
+        return names;
+    }
+
+    /// Calculate tree without variables with the declarator, which built it
+    pub fn calculate(&self, declarator: &ExpressionDeclarator<T>) -> Result<T, Token<T>> {
+        return self.calculate_with(declarator, &HashMap::new());
+    }
+
+    /// Calculate tree with values of variables with the declarator, which built it.
+    /// Shared nodes are calculated once.
+    pub fn calculate_with(&self, declarator: &ExpressionDeclarator<T>, variables: &HashMap<String, T>) -> Result<T, Token<T>> {
+        if self.shared {
+            let memo = RefCell::new(vec![None; self.nodes.len()]);
+            return self.calculate_node(self.root, declarator, variables, Some(&memo));
+        }
+
+        return self.calculate_node(self.root, declarator, variables, None);
+    }
+
+    /// Calculate subtree with explicit stack, operands of lazy operations are calculated by nested calls.
+    /// Values of operations are kept in memo, when it's given.
+    fn calculate_node(
+        &self,
+        id: NodeId,
+        declarator: &ExpressionDeclarator<T>,
+        variables: &HashMap<String, T>,
+        memo: Option<&RefCell<Vec<Option<T>>>>,
+    ) -> Result<T, Token<T>> {
+        let mut frames: Vec<(NodeId, usize)> = vec![(id, 0)];
+        let mut values: Vec<T> = Vec::new();
+
+        while let Some((id, next)) = frames.pop() {
+            let op = match &self.nodes[id as usize] {
+                FlatNode::Primitive { val, .. } | FlatNode::Constant { val, .. } => {
+                    values.push(val.clone());
+                    continue;
+                }
+                FlatNode::Variable { name, .. } => match variables.get(self.text(*name)) {
+                    Some(val) => {
+                        values.push(val.clone());
+                        continue;
+                    }
+                    None => return Result::Err(self.token(id, declarator)),
+                },
+                FlatNode::Operation { op, .. } => &declarator.operations[*op as usize],
+            };
+
+            if let Some(val) = memo.and_then(|memo| memo.borrow()[id as usize].clone()) {
+                values.push(val);
+                continue;
+            }
+
+            let operands = self.operands(id);
+            let val = match &op.executor {
+                Executor::Eager(_) if next < operands.len() => {
+                    frames.push((id, next + 1));
+                    frames.push((operands[next], 0));
+                    continue;
+                }
+                Executor::Eager(executor) => {
+                    let first = values.len() - operands.len();
+                    let value = executor(&values[first..]);
+                    values.truncate(first);
+                    value
+                }
+                Executor::Lazy(executor) => {
+                    let thunks: Vec<BoxedThunk<T>> = operands.iter()
+                        .map(|operand| Box::new(move || self.calculate_node(*operand, declarator, variables, memo)) as BoxedThunk<T>)
+                        .collect();
+                    let refs: Vec<Thunk<T>> = thunks.iter().map(|it| it.as_ref()).collect();
+                    executor(&refs)?
+                }
+            };
+            if let Some(memo) = memo {
+                memo.borrow_mut()[id as usize] = Some(val.clone());
+            }
+            values.push(val);
+        }
+
+        return Result::Ok(values.pop().unwrap());
+    }
+
+    fn text(&self, range: (u32, u32)) -> &str {
+        return &self.texts[range.0 as usize..range.1 as usize];
+    }
+}
+
+/// Builder of `FlatAst` for parser and conversion
+pub(crate) struct FlatAstBuilder<'a, T: Clone> {
+    declarator: &'a ExpressionDeclarator<T>,
+    ast: FlatAst<T>,
+    /// Built nodes by keys, when equal subexpressions are shared
+    built: Option<HashMap<SharedKey, NodeId>>,
+}
+
+impl<'a, T: 'static + Clone> FlatAstBuilder<'a, T> {
+    pub(crate) fn new(declarator: &'a ExpressionDeclarator<T>) -> FlatAstBuilder<'a, T> {
+        let ast = FlatAst { nodes: Vec::new(), operands: Vec::new(), texts: String::new(), root: 0, shared: false };
+
+        return FlatAstBuilder { declarator, ast, built: None };
+    }
+
+    /// Builder, which returns the node built before instead of an equal one
+    pub(crate) fn sharing(declarator: &'a ExpressionDeclarator<T>) -> FlatAstBuilder<'a, T> {
+        let mut builder = FlatAstBuilder::new(declarator);
+        builder.built = Some(HashMap::new());
+
+        return builder;
+    }
+
+    pub(crate) fn finish(mut self, root: NodeId) -> FlatAst<T> {
+        self.ast.root = root;
+
+        return self.ast;
+    }
+
+    fn push(&mut self, node: FlatNode<T>) -> NodeId {
+        self.ast.nodes.push(node);
+
+        return (self.ast.nodes.len() - 1) as NodeId;
+    }
+
+    fn push_text(&mut self, text: &str) -> (u32, u32) {
+        let start = self.ast.texts.len() as u32;
+        self.ast.texts.push_str(text);
+
+        return (start, self.ast.texts.len() as u32);
+    }
+
+    fn operation_id(&self, op: &Operation<T>, token: &Token<T>) -> Result<OperationId, Token<T>> {
+        return self.declarator.operation_id(op).ok_or_else(|| token.clone());
+    }
+
+    /// Purity of the declared operation, as trees built before `set_impure` keep pure copies of it
+    fn is_pure(&self, op: OperationId) -> bool {
+        return self.declarator.operations[op as usize].pure;
+    }
+
+    /// Key of node, when subexpressions are shared
+    fn key(&self, key: impl FnOnce() -> SharedKey) -> Option<SharedKey> {
+        return self.built.as_ref().map(|_| key());
+    }
+
+    /// Equal node, which was built before
+    fn find(&mut self, key: &Option<SharedKey>) -> Option<NodeId> {
+        let id = *self.built.as_ref()?.get(key.as_ref()?)?;
+        self.ast.shared = true;
+
+        return Some(id);
+    }
+
+    fn remember(&mut self, key: Option<SharedKey>, id: NodeId) -> NodeId {
+        if let (Some(built), Some(key)) = (&mut self.built, key) {
+            built.insert(key, id);
+        }
+
+        return id;
+    }
+}
+
+impl<T: 'static + Clone> AstBuilder<T> for FlatAstBuilder<'_, T> {
+    type Node = NodeId;
+
+    fn primitive(&mut self, val: T, token: &Token<T>) -> NodeId {
+        let key = self.key(|| SharedKey::Primitive(token.get_value()));
+        if let Some(id) = self.find(&key) {
+            return id;
+        }
+        let text = self.push_text(token.text());
+        let id = self.push(FlatNode::Primitive { val, text, span: token.get_span() });
+
+        return self.remember(key, id);
+    }
+
+    fn variable(&mut self, name: &str, token: &Token<T>) -> NodeId {
+        let key = self.key(|| SharedKey::Variable(name.to_string()));
+        if let Some(id) = self.find(&key) {
+            return id;
+        }
+        let name = self.push_text(name);
+        let id = self.push(FlatNode::Variable { name, span: token.get_span() });
+
+        return self.remember(key, id);
+    }
+
+    fn constant(&mut self, val: T, token: &Token<T>) -> Result<NodeId, Token<T>> {
+        let op = match token {
+            Token::Operation { val: op, .. } => self.operation_id(op, token)?,
+            _ => return Result::Err(token.clone()),
+        };
+        let key = if self.is_pure(op) { self.key(|| SharedKey::Constant(op)) } else { None };
+        if let Some(id) = self.find(&key) {
+            return Result::Ok(id);
+        }
+        let id = self.push(FlatNode::Constant { val, op, span: token.get_span() });
+
+        return Result::Ok(self.remember(key, id));
+    }
+
+    fn operation(&mut self, op: Arc<Operation<T>>, operands: Vec<NodeId>, token: &Token<T>) -> Result<NodeId, Token<T>> {
+        let op = self.operation_id(&op, token)?;
+        let key = if self.is_pure(op) { self.key(|| SharedKey::Operation(op, operands.clone())) } else { None };
+        if let Some(id) = self.find(&key) {
+            return Result::Ok(id);
+        }
+        let first_operand = self.ast.operands.len() as u32;
+        let count = operands.len() as u8;
+        self.ast.operands.extend(operands);
+        let id = self.push(FlatNode::Operation { op, first_operand, operands: count, span: token.get_span() });
+
+        return Result::Ok(self.remember(key, id));
+    }
+
+    fn type_of(&self, node: &NodeId) -> Option<ValueType> {
+        let op = match &self.ast.nodes[*node as usize] {
+            FlatNode::Primitive { val, .. } => return self.declarator.handler.value_type(val),
+            FlatNode::Variable { .. } => return None,
+            FlatNode::Constant { op, .. } | FlatNode::Operation { op, .. } => &self.declarator.operations[*op as usize],
+        };
+
+        return op.types.as_ref().map(|types| types.result);
+    }
+
+    fn token(&self, node: &NodeId) -> Token<T> {
+        return self.ast.token(*node, self.declarator);
+    }
+}
@@ -1,11 +1,20 @@
+#![allow(clippy::needless_return)]
+
 pub mod declarator;
 pub mod calculator;
 pub mod ast;
+pub mod flat_ast;
+pub mod bytecode;
 pub mod token;
 pub mod operation;
+mod trie;
 mod tests;
 
 #[cfg(feature = "f64_calculator")]
 pub mod f64_calculator;
 #[cfg(feature = "bool_calculator")]
 pub mod bool_calculator;
+#[cfg(feature = "mixed_calculator")]
+pub mod mixed_calculator;
+#[cfg(feature = "parallel")]
+mod parallel;
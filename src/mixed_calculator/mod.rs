@@ -0,0 +1,227 @@
+use crate::bool_calculator::{boolean_calculator, BoolHandler};
+use crate::calculator::{BoxedThunk, Executor, LazyOperationExecutor, Thunk};
+use crate::declarator::{ExpressionDeclarator, PrimitiveHandler, ULTIMATE_ORDER};
+use crate::f64_calculator::{f64_calculator, F64Handler};
+use crate::operation::{Operation, OperationType, TypeSignature, ValueType};
+use crate::token::Token;
+use std::cell::RefCell;
+use std::sync::Arc;
+
+pub const NUMBER: ValueType = "number";
+pub const BOOL: ValueType = "bool";
+
+/// Priority of `==` and `!=`
+pub const EQUALITY_ORDER: u8 = 90;
+/// Priority of `<`, `<=`, `>` and `>=`
+pub const COMPARISON_ORDER: u8 = 95;
+/// Priority of `x ? y : z`
+pub const CONDITIONAL_ORDER: u8 = 5;
+/// Arithmetic operations are shifted above comparisons, while logical ones stay below them
+const ARITHMETIC_SHIFT: u8 = 100;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Value {
+    Number(f64),
+    Bool(bool),
+}
+
+impl Value {
+    pub fn as_number(&self) -> f64 {
+        return match self {
+            Value::Number(val) => *val,
+            Value::Bool(val) => if *val { 1.0 } else { 0.0 },
+        };
+    }
+
+    pub fn as_bool(&self) -> bool {
+        return match self {
+            Value::Number(val) => *val != 0.0,
+            Value::Bool(val) => *val,
+        };
+    }
+}
+
+struct ValueHandler {
+    number: F64Handler,
+    boolean: BoolHandler,
+}
+
+impl PrimitiveHandler<Value> for ValueHandler {
+    fn from_string(&self, input: &String) -> Result<Value, ()> {
+        if let Ok(val) = self.number.from_string(input) {
+            return Result::Ok(Value::Number(val));
+        }
+
+        return self.boolean.from_string(input).map(Value::Bool);
+    }
+
+    fn can_start_with(&self, input: String) -> bool {
+        return self.number.can_start_with(input.clone()) || self.boolean.can_start_with(input);
+    }
+
+    /// Number is taken when both literals have the same length, as in `from_string`
+    fn longest(&self, input: &[char]) -> (Option<(usize, Value)>, usize) {
+        let (number, number_reach) = self.number.longest(input);
+        let (boolean, boolean_reach) = self.boolean.longest(input);
+        let longest = match (number, boolean) {
+            (Some((length, val)), Some((boolean_length, _))) if length >= boolean_length => Some((length, Value::Number(val))),
+            (_, Some((length, val))) => Some((length, Value::Bool(val))),
+            (Some((length, val)), None) => Some((length, Value::Number(val))),
+            (None, None) => None,
+        };
+
+        return (longest, number_reach.max(boolean_reach));
+    }
+
+    fn value_type(&self, value: &Value) -> Option<ValueType> {
+        return match value {
+            Value::Number(_) => Some(NUMBER),
+            Value::Bool(_) => Some(BOOL),
+        };
+    }
+}
+
+/// Calculator over numbers and booleans.
+/// Contains all operations of `f64_calculator` and `boolean_calculator` together with comparisons,
+/// e.g. `1 < 2 & 3 >= 3`, and conditionals `x > 0 ? 1 : 2` or `if(x > 0, 1, 2)`.
+/// Operands are type checked while AST is built. Only the chosen branch of a conditional is calculated.
+/// Boolean operations which signatures are already taken by arithmetic ones (`^`) are not imported,
+/// use word operators instead, e.g. `xor`.
+pub fn mixed_calculator() -> ExpressionDeclarator<Value> {
+    let mut calculator = ExpressionDeclarator::<Value>::new(Box::new(ValueHandler { number: F64Handler::new(), boolean: BoolHandler::new() }));
+    calculator.add_line_comment("#".to_string());
+
+    import(&mut calculator, f64_calculator(), NUMBER, Value::Number, Value::as_number, ARITHMETIC_SHIFT);
+    import(&mut calculator, boolean_calculator(), BOOL, Value::Bool, Value::as_bool, 0);
+
+    add_comparison(&mut calculator, "<", "Less than", |op1, op2| op1 < op2);
+    add_comparison(&mut calculator, "<=", "Less than or equal", |op1, op2| op1 <= op2);
+    add_comparison(&mut calculator, ">", "Greater than", |op1, op2| op1 > op2);
+    add_comparison(&mut calculator, ">=", "Greater than or equal", |op1, op2| op1 >= op2);
+
+    for &op_type in [NUMBER, BOOL].iter() {
+        calculator.add_typed(
+            "==".to_string(),
+            "Equal".to_string(),
+            OperationType::Infix,
+            Executor::Eager(Box::new(|ops: &[Value]| { Value::Bool(ops[0] == ops[1]) })),
+            TypeSignature { operands: vec![op_type, op_type], result: BOOL },
+            EQUALITY_ORDER,
+        );
+        calculator.add_typed(
+            "!=".to_string(),
+            "Not equal".to_string(),
+            OperationType::Infix,
+            Executor::Eager(Box::new(|ops: &[Value]| { Value::Bool(ops[0] != ops[1]) })),
+            TypeSignature { operands: vec![op_type, op_type], result: BOOL },
+            EQUALITY_ORDER,
+        );
+
+        let mut ternary = Operation::new(
+            "?".to_string(),
+            "Conditional, 'condition ? then : else'".to_string(),
+            OperationType::Ternary,
+            Executor::Lazy(Box::new(choose)),
+            3,
+            CONDITIONAL_ORDER,
+        );
+        ternary.delimiter = Some(":".to_string());
+        ternary.types = Some(TypeSignature { operands: vec![BOOL, op_type, op_type], result: op_type });
+        calculator.add_operation(ternary);
+
+        calculator.add_typed(
+            "if".to_string(),
+            "Conditional, 'if(condition, then, else)'".to_string(),
+            OperationType::Prefix,
+            Executor::Lazy(Box::new(choose)),
+            TypeSignature { operands: vec![BOOL, op_type, op_type], result: op_type },
+            ULTIMATE_ORDER,
+        );
+    }
+
+    return calculator;
+}
+
+fn choose(operands: &[Thunk<Value>]) -> Result<Value, Token<Value>> {
+    return if operands[0]()?.as_bool() { operands[1]() } else { operands[2]() };
+}
+
+fn add_comparison(calculator: &mut ExpressionDeclarator<Value>, signature: &str, description: &str, compare: fn(f64, f64) -> bool) {
+    calculator.add_typed(
+        signature.to_string(),
+        description.to_string(),
+        OperationType::Infix,
+        Executor::Eager(Box::new(move |ops: &[Value]| { Value::Bool(compare(ops[0].as_number(), ops[1].as_number())) })),
+        TypeSignature { operands: vec![NUMBER, NUMBER], result: BOOL },
+        COMPARISON_ORDER,
+    );
+}
+
+fn import<U: 'static + Clone>(
+    target: &mut ExpressionDeclarator<Value>,
+    source: ExpressionDeclarator<U>,
+    value_type: ValueType,
+    wrap: fn(U) -> Value,
+    unwrap: fn(&Value) -> U,
+    shift: u8,
+) {
+    for operation in source.operations.into_iter().map(|it| Arc::try_unwrap(it).unwrap_or_else(|it| Operation::clone(&it))) {
+        if target.signatures.operation(&operation.signature, &operation.op_type).is_some() {
+            continue;
+        }
+
+        let executor = match operation.executor {
+            Executor::Eager(executor) => Executor::Eager(Box::new(move |ops: &[Value]| {
+                wrap(executor(&ops.iter().map(unwrap).collect::<Vec<U>>()))
+            })),
+            Executor::Lazy(executor) => Executor::Lazy(Box::new(move |thunks: &[Thunk<Value>]| {
+                import_lazy(thunks, &*executor, wrap, unwrap)
+            })),
+        };
+        let mut imported = Operation::new(
+            operation.signature,
+            operation.description,
+            operation.op_type,
+            executor,
+            operation.operands,
+            operation.priority.saturating_add(shift),
+        );
+        imported.types = Some(TypeSignature { operands: vec![value_type; operation.operands as usize], result: value_type });
+        imported.delimiter = operation.delimiter;
+        imported.associativity = operation.associativity;
+        target.add_operation(imported);
+    }
+}
+
+/// Call lazy executor of other domain.
+/// Failure of a thunk is kept aside and returned as is, instead of an error of the other domain.
+fn import_lazy<U: 'static + Clone>(
+    thunks: &[Thunk<Value>],
+    executor: &dyn LazyOperationExecutor<U>,
+    wrap: fn(U) -> Value,
+    unwrap: fn(&Value) -> U,
+) -> Result<Value, Token<Value>> {
+    let failure: RefCell<Option<Token<Value>>> = RefCell::new(None);
+    let result = {
+        let failure = &failure;
+        let inner: Vec<BoxedThunk<U>> = thunks.iter()
+            .map(|thunk| Box::new(move || match thunk() {
+                Ok(value) => Ok(unwrap(&value)),
+                Err(error) => {
+                    let span = error.get_span();
+                    let val = error.get_value();
+                    *failure.borrow_mut() = Some(error);
+                    Err(Token::Unknown { span, val })
+                }
+            }) as BoxedThunk<U>)
+            .collect();
+        let refs: Vec<Thunk<U>> = inner.iter().map(|it| it.as_ref()).collect();
+        executor(&refs)
+    };
+
+    if let Some(error) = failure.into_inner() {
+        return Result::Err(error);
+    }
+
+    return result.map(wrap).map_err(|error| Token::Unknown { span: error.get_span(), val: error.get_value() });
+}
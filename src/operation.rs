@@ -1,12 +1,32 @@
-use crate::calculator::OperationExecutor;
+use crate::calculator::{Executor, VectorOperationExecutor};
 
 #[derive(Clone)]
-#[derive(PartialEq)]
+#[derive(PartialEq, Eq, Hash)]
 pub enum OperationType {
     Constant,
     Prefix,
     Postfix,
     Infix,
+    /// Mixfix operation with three operands, e.g. `x ? y : z`
+    Ternary,
+}
+
+/// Grouping of operations with the same priority.
+/// `Left` reads `a-b-c` as `(a-b)-c`, `Right` reads `a^b^c` as `a^(b^c)`
+#[derive(Clone, PartialEq, Debug)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+/// Name of a value type inside a domain, e.g. "number" or "bool"
+pub type ValueType = &'static str;
+
+/// Types of operands and result of a typed operation
+#[derive(Clone, PartialEq, Debug)]
+pub struct TypeSignature {
+    pub operands: Vec<ValueType>,
+    pub result: ValueType,
 }
 
 pub struct Operation<T: Clone> {
@@ -15,7 +35,15 @@ pub struct Operation<T: Clone> {
     pub op_type: OperationType,
     pub operands: u8,
     pub priority: u8,
-    pub executor: Box<dyn OperationExecutor<T>>,
+    pub associativity: Associativity,
+    pub executor: Executor<T>,
+    pub types: Option<TypeSignature>,
+    /// Second part of a ternary operation signature, e.g. ":" for "?"
+    pub delimiter: Option<String>,
+    /// Optional executor over columns for batch calculation. Eager executor is called per row without it.
+    pub vectorized: Option<Box<dyn VectorOperationExecutor<T>>>,
+    /// Result depends on operands only, so equal subexpressions are calculated once. False for e.g. random numbers.
+    pub pure: bool,
 }
 
 impl<T: 'static + Clone> Clone for Operation<T> {
@@ -26,21 +54,66 @@ impl<T: 'static + Clone> Clone for Operation<T> {
             op_type: self.op_type.clone(),
             operands: self.operands,
             priority: self.priority,
+            associativity: self.associativity.clone(),
             executor: self.executor.clone(),
+            types: self.types.clone(),
+            delimiter: self.delimiter.clone(),
+            vectorized: self.vectorized.clone(),
+            pure: self.pure,
         };
     }
 }
 
 impl<T: 'static + Clone> Operation<T> {
+    /// Create operation. Ternary operations are right associative, others are left associative.
+    pub fn new(
+        signature: String,
+        description: String,
+        op_type: OperationType,
+        executor: Executor<T>,
+        operands: u8,
+        priority: u8,
+    ) -> Operation<T> {
+        let associativity = if op_type == OperationType::Ternary { Associativity::Right } else { Associativity::Left };
+
+        return Operation {
+            signature,
+            description,
+            op_type,
+            operands,
+            priority,
+            associativity,
+            executor,
+            types: None,
+            delimiter: None,
+            vectorized: None,
+            pure: true,
+        };
+    }
+
     pub fn pretty_print(&self) -> String {
         return match self.op_type.clone() {
             OperationType::Constant => self.signature.clone(),
+            OperationType::Prefix if self.is_function() => {
+                let names: Vec<String> = match self.operands {
+                    2 => vec!["x".to_string(), "y".to_string()],
+                    3 => vec!["x".to_string(), "y".to_string(), "z".to_string()],
+                    n => (1..=n).map(|i| format!("x{}", i)).collect(),
+                };
+                format!("{}({})", &self.signature, names.join(", "))
+            }
             OperationType::Prefix => format!("{}(x)", &self.signature),
             OperationType::Postfix => format!("x{}", &self.signature),
             OperationType::Infix => format!("x{}y", &self.signature),
+            OperationType::Ternary => format!("x{}y{}z", &self.signature, self.delimiter.clone().unwrap_or_default()),
         };
     }
 
+    /// Prefix operation with several operands, called as `f(x, y)`
+    pub fn is_function(&self) -> bool {
+        return self.op_type == OperationType::Prefix && self.operands > 1;
+    }
+
     pub fn description(&self) -> String {
         return self.description.clone();
     }
@@ -48,4 +121,18 @@ impl<T: 'static + Clone> Operation<T> {
     pub fn priority(&self) -> u8 {
         return self.priority;
     }
+
+    /// Check if operation accepts operands of given types.
+    /// Untyped operations and operands of unknown type are always accepted.
+    pub fn accepts(&self, operands: &[Option<ValueType>]) -> bool {
+        let types = match &self.types {
+            Some(types) => types,
+            None => return true,
+        };
+
+        return types.operands.len() == operands.len()
+            && types.operands.iter().zip(operands.iter()).all(|(expected, actual)| {
+            actual.is_none() || actual.unwrap() == *expected
+        });
+    }
 }
\ No newline at end of file
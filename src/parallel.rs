@@ -0,0 +1,35 @@
+//! Calculation of programs over many sets of variables in scoped threads
+
+use crate::bytecode::{Program, Vm};
+use crate::declarator::ExpressionDeclarator;
+use crate::token::Token;
+use std::panic;
+use std::thread;
+
+impl<T: 'static + Clone + Send + Sync> Program<T> {
+    /// Calculate program for every set of values of variables in order of `Program::variables`.
+    /// Sets are split into contiguous chunks, one per thread, so results are in order of sets.
+    /// The error of the first failed set is reported. At least one thread is used.
+    pub fn calculate_parallel(&self, declarator: &ExpressionDeclarator<T>, bindings: &[Vec<T>], threads: usize) -> Result<Vec<T>, Token<T>> {
+        if bindings.is_empty() {
+            return Result::Ok(Vec::new());
+        }
+        let chunk = bindings.len().div_ceil(threads.max(1));
+
+        return thread::scope(|scope| {
+            let handles: Vec<_> = bindings.chunks(chunk)
+                .map(|sets| scope.spawn(move || {
+                    let mut vm = Vm::new();
+                    sets.iter().map(|set| vm.run(self, declarator, set)).collect::<Result<Vec<T>, Token<T>>>()
+                }))
+                .collect();
+
+            let mut results = Vec::with_capacity(bindings.len());
+            for handle in handles {
+                results.extend(handle.join().unwrap_or_else(|error| panic::resume_unwind(error))?);
+            }
+
+            return Result::Ok(results);
+        });
+    }
+}
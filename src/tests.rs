@@ -1,4 +1,5 @@
 #[cfg(test)]
+#[allow(clippy::module_inception, clippy::bool_assert_comparison, clippy::unnecessary_unwrap, clippy::clone_on_copy)]
 mod tests {
     use crate::declarator::{ExpressionDeclarator, HIGH_ORDER, LOW_ORDER, PrimitiveHandler};
 
@@ -152,4 +153,1226 @@ mod tests {
         let result = calculator.calculate("---2");
         assert_eq!(result.ok().unwrap(), -2)
     }
-}
\ No newline at end of file
+
+    #[test]
+    #[cfg(feature = "mixed_calculator")]
+    fn it_compares_numbers_into_booleans() {
+        use crate::mixed_calculator::{mixed_calculator, Value, BOOL, NUMBER};
+
+        let calculator = mixed_calculator();
+
+        assert_eq!(calculator.calculate("1 < 2").ok().unwrap(), Value::Bool(true));
+        assert_eq!(calculator.calculate("2 + 3 == 5").ok().unwrap(), Value::Bool(true));
+        assert_eq!(calculator.calculate("5 > 0 & 5 < 10").ok().unwrap(), Value::Bool(true));
+        assert_eq!(calculator.calculate("5 > 0 & 15 < 10 | true").ok().unwrap(), Value::Bool(true));
+        assert_eq!(calculator.calculate("1 < 2 != false").ok().unwrap(), Value::Bool(true));
+        assert_eq!(calculator.calculate("2^3 * 2").ok().unwrap(), Value::Number(16.0));
+
+        let ast = calculator.build_ast("1 + 2 >= 3").ok().unwrap();
+        assert_eq!(calculator.type_of(&ast), Some(BOOL));
+        let ast = calculator.build_ast("-pi * 2").ok().unwrap();
+        assert_eq!(calculator.type_of(&ast), Some(NUMBER));
+    }
+
+    #[test]
+    #[cfg(feature = "mixed_calculator")]
+    fn it_fails_on_type_mismatch() {
+        use crate::mixed_calculator::mixed_calculator;
+
+        let calculator = mixed_calculator();
+
+        let token = calculator.build_ast("1 + true").err().unwrap();
+        assert_eq!(token.get_pos(), 2);
+        assert_eq!(token.get_value(), "+".to_string());
+
+        let token = calculator.build_ast("(1 < 2) < 3").err().unwrap();
+        assert_eq!(token.get_pos(), 8);
+
+        let token = calculator.build_ast("!5").err().unwrap();
+        assert_eq!(token.get_value(), "!".to_string());
+    }
+
+    #[test]
+    fn it_calls_functions() {
+        let mut calculator = ExpressionDeclarator::<i32>::new(Box::new(IntHandler {}));
+        calculator.add_infix(
+            "+".to_string(),
+            "+".to_string(),
+            Box::new(|op1, op2| { op1 + op2 }),
+            LOW_ORDER,
+        );
+        calculator.add_function(
+            "max".to_string(),
+            "Maximum".to_string(),
+            Box::new(|ops: &[i32]| { *ops.iter().max().unwrap() }),
+            3,
+        );
+
+        assert_eq!(calculator.calculate("max(1, 7+1, 3)").ok().unwrap(), 8);
+        assert_eq!(calculator.calculate("1 + max(1, max(4, 5, 6), (3))+1").ok().unwrap(), 8);
+
+        let token = calculator.calculate("max(1, 2)").err().unwrap();
+        assert_eq!(token.get_value(), "max".to_string());
+
+        let token = calculator.calculate("(1, 2)").err().unwrap();
+        assert_eq!(token.get_value(), ")".to_string());
+
+        let token = calculator.calculate("1, 2").err().unwrap();
+        assert_eq!(token.get_value(), ",".to_string());
+    }
+
+    #[test]
+    fn it_calculates_only_chosen_branch() {
+        use crate::calculator::Thunk;
+        use std::sync::atomic::{AtomicI32, Ordering};
+        use std::sync::Arc;
+
+        let calls = Arc::new(AtomicI32::new(0));
+        let counter = calls.clone();
+        let mut calculator = ExpressionDeclarator::<i32>::new(Box::new(IntHandler {}));
+        calculator.add_infix(
+            "+".to_string(),
+            "+".to_string(),
+            Box::new(|op1, op2| { op1 + op2 }),
+            LOW_ORDER,
+        );
+        calculator.add_prefix(
+            "count".to_string(),
+            "Count calls".to_string(),
+            Box::new(move |op1| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                op1
+            }),
+            HIGH_ORDER,
+        );
+        calculator.add_ternary(
+            "?".to_string(),
+            ":".to_string(),
+            "Conditional".to_string(),
+            Box::new(|ops: &[Thunk<i32>]| { if ops[0]()? != 0 { ops[1]() } else { ops[2]() } }),
+            1,
+        );
+
+        assert_eq!(calculator.calculate("1 ? count 2 : count 3").ok().unwrap(), 2);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(calculator.calculate("0 ? count 2 : 1 ? count 3 : count 4").ok().unwrap(), 3);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(calculator.calculate("(1 ? 0 : 1) ? 5 : 6 + 1").ok().unwrap(), 7);
+
+        let token = calculator.calculate("1 ? 2").err().unwrap();
+        assert_eq!(token.get_value(), "?".to_string());
+
+        let token = calculator.calculate("1 : 2").err().unwrap();
+        assert_eq!(token.get_value(), ":".to_string());
+    }
+
+    #[test]
+    #[cfg(feature = "mixed_calculator")]
+    fn it_calculates_conditionals() {
+        use crate::mixed_calculator::{mixed_calculator, Value};
+
+        let calculator = mixed_calculator();
+
+        assert_eq!(calculator.calculate("2 > 1 ? 10 : 20").ok().unwrap(), Value::Number(10.0));
+        assert_eq!(calculator.calculate("if(2 < 1, 10, 20) * 2").ok().unwrap(), Value::Number(40.0));
+        assert_eq!(calculator.calculate("if(true, 1 < 2, false)").ok().unwrap(), Value::Bool(true));
+        assert_eq!(calculator.calculate("1 > 2 ? 1 : 2 > 3 ? 2 : 3").ok().unwrap(), Value::Number(3.0));
+
+        assert!(calculator.calculate("1 ? 2 : 3").is_err());
+        assert!(calculator.calculate("if(true, 1, false)").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "bool_calculator")]
+    fn it_short_circuits_boolean_operations() {
+        use crate::bool_calculator::boolean_calculator;
+        use std::sync::atomic::{AtomicI32, Ordering};
+        use std::sync::Arc;
+
+        let calls = Arc::new(AtomicI32::new(0));
+        let counter = calls.clone();
+        let mut calculator = boolean_calculator();
+        calculator.add_prefix(
+            "?".to_string(),
+            "Count calls".to_string(),
+            Box::new(move |op1| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                op1
+            }),
+            HIGH_ORDER,
+        );
+
+        assert_eq!(calculator.calculate("false & ?true").ok().unwrap(), false);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        assert_eq!(calculator.calculate("true | ?true").ok().unwrap(), true);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        assert_eq!(calculator.calculate("?true & ?false").ok().unwrap(), false);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(calculator.calculate("?false | ?true").ok().unwrap(), true);
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+        assert_eq!(calculator.calculate("?true ^ ?true").ok().unwrap(), false);
+        assert_eq!(calls.load(Ordering::SeqCst), 6);
+    }
+
+    #[test]
+    #[cfg(feature = "bool_calculator")]
+    fn it_respects_boolean_precedence() {
+        use crate::bool_calculator::boolean_calculator;
+
+        let calculator = boolean_calculator();
+
+        assert_eq!(calculator.calculate("true | false & false").ok().unwrap(), true);
+        assert_eq!(calculator.calculate("(true | false) & false").ok().unwrap(), false);
+        assert_eq!(calculator.calculate("!false & false").ok().unwrap(), false);
+        assert_eq!(calculator.calculate("true ^ true | true").ok().unwrap(), true);
+        assert_eq!(calculator.calculate("true | true ^ true").ok().unwrap(), true);
+        assert_eq!(calculator.calculate("false -> false -> false").ok().unwrap(), true);
+        assert_eq!(calculator.calculate("(false -> false) -> false").ok().unwrap(), false);
+        assert_eq!(calculator.calculate("true -> false <-> false").ok().unwrap(), true);
+        assert_eq!(calculator.calculate("1 & 0 <-> 0").ok().unwrap(), true);
+    }
+
+    #[test]
+    #[cfg(feature = "bool_calculator")]
+    fn it_supports_boolean_words() {
+        use crate::bool_calculator::{boolean_calculator, boolean_calculator_with, BoolHandler};
+
+        let calculator = boolean_calculator();
+
+        assert_eq!(calculator.calculate("not true or true and false").ok().unwrap(), false);
+        assert_eq!(calculator.calculate("true xor false").ok().unwrap(), true);
+        assert_eq!(calculator.calculate("true nand true").ok().unwrap(), false);
+        assert_eq!(calculator.calculate("false nor false").ok().unwrap(), true);
+        assert_eq!(calculator.calculate("TRUE and True").ok().unwrap(), true);
+
+        let calculator = boolean_calculator_with(BoolHandler::new().with_literals("yes", "no"));
+        assert_eq!(calculator.calculate("yes and not no").ok().unwrap(), true);
+        assert_eq!(calculator.calculate("no nor no").ok().unwrap(), true);
+
+        let calculator = boolean_calculator_with(BoolHandler::new().with_literals("T", "F"));
+        assert_eq!(calculator.calculate("t & !f").ok().unwrap(), true);
+        assert!(calculator.calculate("yes").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "f64_calculator")]
+    fn it_respects_associativity() {
+        use crate::f64_calculator::f64_calculator;
+
+        let calculator = f64_calculator();
+
+        assert_eq!(calculator.calculate("8-2-1").ok().unwrap(), 5.0);
+        assert_eq!(calculator.calculate("8/2/2").ok().unwrap(), 2.0);
+        assert_eq!(calculator.calculate("2-3+4").ok().unwrap(), 3.0);
+        assert_eq!(calculator.calculate("12/2*3").ok().unwrap(), 18.0);
+        assert_eq!(calculator.calculate("8-(2-1)").ok().unwrap(), 7.0);
+        assert_eq!(calculator.calculate("2^3^2").ok().unwrap(), 512.0);
+        assert_eq!(calculator.calculate("(2^3)^2").ok().unwrap(), 64.0);
+    }
+
+    #[test]
+    fn it_calculates_variables() {
+        use std::collections::HashMap;
+
+        let mut calculator = ExpressionDeclarator::<i32>::new(Box::new(IntHandler {}));
+        calculator.add_infix(
+            "+".to_string(),
+            "+".to_string(),
+            Box::new(|op1, op2| { op1 + op2 }),
+            LOW_ORDER,
+        );
+        calculator.add_variable("x".to_string());
+
+        let mut variables = HashMap::new();
+        variables.insert("x".to_string(), 3);
+        assert_eq!(calculator.calculate_with("x + 1 + x", &variables).ok().unwrap(), 7);
+
+        let token = calculator.calculate("1 + x").err().unwrap();
+        assert_eq!(token.get_pos(), 4);
+        assert_eq!(token.get_value(), "x".to_string());
+
+        assert!(calculator.calculate_with("y + 1", &variables).is_err());
+
+        calculator.set_free_variables(true);
+        variables.insert("y_1".to_string(), 4);
+        assert_eq!(calculator.calculate_with("y_1 + x", &variables).ok().unwrap(), 7);
+
+        let ast = calculator.build_ast("b + a + b").ok().unwrap();
+        assert_eq!(ast.variables(), vec!["b".to_string(), "a".to_string()]);
+    }
+
+    #[test]
+    #[cfg(feature = "bool_calculator")]
+    fn it_builds_truth_table() {
+        use crate::bool_calculator::boolean_calculator;
+        use crate::bool_calculator::truth_table::{truth_table, TruthTableError};
+
+        let mut calculator = boolean_calculator();
+        calculator.set_free_variables(true);
+
+        let table = truth_table(&calculator, "b -> a", 4).ok().unwrap();
+        assert_eq!(table.variables, vec!["b".to_string(), "a".to_string()]);
+        assert_eq!(table.rows.iter().map(|it| it.result).collect::<Vec<bool>>(), vec![true, true, false, true]);
+        assert_eq!(table.rows[2].values, vec![true, false]);
+
+        assert_eq!(
+            table.to_csv(),
+            "b,a,result\nfalse,false,true\nfalse,true,true\ntrue,false,false\ntrue,true,true"
+        );
+        assert_eq!(
+            table.to_text(),
+            "b     | a     | result\n------+-------+-------\nfalse | false | true\nfalse | true  | true\ntrue  | false | false\ntrue  | true  | true"
+        );
+
+        let table = truth_table(&calculator, "true", 0).ok().unwrap();
+        assert_eq!(table.rows.len(), 1);
+
+        match truth_table(&calculator, "a & b & c", 2) {
+            Err(TruthTableError::TooManyVariables { count, max }) => assert_eq!((count, max), (3, 2)),
+            _ => panic!("Too many variables expected"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "f64_calculator")]
+    fn it_prints_ast_back() {
+        use crate::f64_calculator::locale::Locale;
+        use crate::f64_calculator::{f64_calculator, f64_calculator_with_locale, AngleMode};
+
+        let calculator = f64_calculator();
+
+        for input in ["1 + 2 * 3", "(1 + 2) * 3", "8 - (2 - 1)", "8 - 2 - 1", "2 ^ 3 ^ 2", "(2 ^ 3) ^ 2", "-(1 + 2)", "sqrt(4) * pi"].iter() {
+            let ast = calculator.build_ast(input).ok().unwrap();
+            assert_eq!(ast.pretty_print(), input.to_string());
+        }
+
+        let ast = calculator.build_ast("max(1,2)+log(2,8)").ok().unwrap();
+        assert_eq!(ast.pretty_print(), "max(1, 2) + log(2, 8)");
+        assert_eq!(calculator.calculate(&ast.pretty_print()).ok().unwrap(), 5.0);
+
+        let calculator = f64_calculator_with_locale(Locale::european(), AngleMode::Radians);
+        let ast = calculator.build_ast("max(1,5; 2) + atan2(1; 1,5)").ok().unwrap();
+        assert_eq!(ast.pretty_print_with(&calculator), "max(1,5; 2) + atan2(1; 1,5)");
+        let printed = calculator.build_ast(&ast.pretty_print_with(&calculator)).ok().unwrap();
+        assert_eq!(printed.calculate().ok().unwrap(), ast.calculate().ok().unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "bool_calculator")]
+    fn it_builds_normal_forms() {
+        use crate::bool_calculator::boolean_calculator;
+        use crate::bool_calculator::normal_form::{minimize, to_cnf, to_dnf, NormalFormError};
+        use crate::bool_calculator::truth_table::truth_table;
+        use std::collections::HashMap;
+
+        let mut calculator = boolean_calculator();
+        calculator.set_free_variables(true);
+
+        let ast = calculator.build_ast("a -> b").ok().unwrap();
+        assert_eq!(to_dnf(&calculator, &ast, 8).ok().unwrap().pretty_print(), "!a & !b | !a & b | a & b");
+        assert_eq!(to_cnf(&calculator, &ast, 8).ok().unwrap().pretty_print(), "!a | b");
+        assert_eq!(minimize(&calculator, &ast, 8).ok().unwrap().pretty_print(), "!a | b");
+
+        let ast = calculator.build_ast("a & b | a & !b | a & b & c").ok().unwrap();
+        assert_eq!(minimize(&calculator, &ast, 8).ok().unwrap().pretty_print(), "a");
+
+        let ast = calculator.build_ast("a & !a").ok().unwrap();
+        assert_eq!(minimize(&calculator, &ast, 8).ok().unwrap().pretty_print(), "false");
+        assert_eq!(to_cnf(&calculator, &ast, 8).ok().unwrap().pretty_print(), "a & !a");
+
+        let ast = calculator.build_ast("a | !a").ok().unwrap();
+        assert_eq!(minimize(&calculator, &ast, 8).ok().unwrap().pretty_print(), "true");
+
+        let input = "(a | b) & (a | c) & (b | !c) -> d xor a";
+        let ast = calculator.build_ast(input).ok().unwrap();
+        let expected = truth_table(&calculator, input, 8).ok().unwrap();
+        for form in [to_dnf(&calculator, &ast, 8), to_cnf(&calculator, &ast, 8), minimize(&calculator, &ast, 8)].iter() {
+            let printed = calculator.build_ast(&form.as_ref().ok().unwrap().pretty_print()).ok().unwrap();
+            for row in expected.rows.iter() {
+                let variables: HashMap<String, bool> = expected.variables.iter().cloned().zip(row.values.iter().cloned()).collect();
+                assert_eq!(printed.calculate_with(&variables).ok().unwrap(), row.result);
+            }
+        }
+
+        let names: Vec<String> = (0..14).map(|i| format!("v{}", i)).collect();
+        let ast = calculator.build_ast(&names.join(" xor ")).ok().unwrap();
+        let dnf = to_dnf(&calculator, &ast, 14).ok().unwrap();
+        assert_eq!(dnf.lazy_depth(), 17);
+        let mut variables: HashMap<String, bool> = names.iter().map(|name| (name.clone(), false)).collect();
+        assert_eq!(dnf.calculate_with(&variables).ok().unwrap(), false);
+        variables.insert("v13".to_string(), true);
+        assert_eq!(dnf.calculate_with(&variables).ok().unwrap(), true);
+
+        calculator.set_max_depth(16);
+        match to_dnf(&calculator, &ast, 14) {
+            Err(NormalFormError::TooDeep { depth, max }) => assert_eq!((depth, max), (17, 16)),
+            _ => panic!("TooDeep error expected"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "bool_calculator")]
+    fn it_checks_satisfiability() {
+        use crate::bool_calculator::boolean_calculator;
+        use crate::bool_calculator::satisfiability::{equivalent, implies, satisfiable, Satisfiability, Validity};
+
+        let mut calculator = boolean_calculator();
+        calculator.set_free_variables(true);
+
+        match satisfiable(&calculator, "a & !b & (b | c)", 8).ok().unwrap() {
+            Satisfiability::Satisfiable(witness) => {
+                assert_eq!(witness.get("a"), Some(true));
+                assert_eq!(witness.get("b"), Some(false));
+                assert_eq!(witness.get("c"), Some(true));
+                assert_eq!(calculator.build_ast("a & !b & (b | c)").ok().unwrap().calculate_with(&witness.to_map()).ok().unwrap(), true);
+            }
+            Satisfiability::Unsatisfiable => panic!("Witness expected"),
+        }
+        assert_eq!(satisfiable(&calculator, "a & !a", 8).ok().unwrap(), Satisfiability::Unsatisfiable);
+
+        assert_eq!(equivalent(&calculator, "!(a & b)", "!a | !b", 8).ok().unwrap(), Validity::Valid);
+        assert_eq!(equivalent(&calculator, "a -> b", "b | !a", 8).ok().unwrap(), Validity::Valid);
+        match equivalent(&calculator, "a -> b", "b -> a", 8).ok().unwrap() {
+            Validity::Counterexample(assignment) => {
+                assert_eq!(assignment.variables, vec!["a".to_string(), "b".to_string()]);
+                assert_eq!(assignment.values, vec![false, true]);
+            }
+            Validity::Valid => panic!("Counterexample expected"),
+        }
+
+        assert_eq!(implies(&calculator, "a & b", "a | c", 8).ok().unwrap(), Validity::Valid);
+        match implies(&calculator, "a | b", "a", 8).ok().unwrap() {
+            Validity::Counterexample(assignment) => assert_eq!(assignment.to_map().get("b"), Some(&true)),
+            Validity::Valid => panic!("Counterexample expected"),
+        }
+
+        assert!(satisfiable(&calculator, "a | b | c", 2).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "f64_calculator")]
+    fn it_inserts_implicit_multiplication() {
+        use crate::declarator::LOW_ORDER;
+        use crate::f64_calculator::f64_calculator;
+        use std::collections::HashMap;
+        use std::f64::consts::PI;
+
+        let mut calculator = f64_calculator();
+        assert_eq!(calculator.calculate("2 3").err().unwrap().get_value(), "3".to_string());
+        assert!(calculator.calculate("2pi").is_err());
+
+        calculator.set_free_variables(true);
+        calculator.set_implicit_infix("*".to_string(), LOW_ORDER + 5);
+        let mut variables = HashMap::new();
+        variables.insert("x".to_string(), 2.0);
+        variables.insert("a".to_string(), 5.0);
+        variables.insert("b".to_string(), 3.0);
+
+        assert_eq!(calculator.calculate("2pi").ok().unwrap(), 2.0 * PI);
+        assert_eq!(calculator.calculate_with("3(x+1)", &variables).ok().unwrap(), 9.0);
+        assert_eq!(calculator.calculate_with("(a+b)(a-b)", &variables).ok().unwrap(), 16.0);
+        assert_eq!(calculator.calculate_with("2sin(x)", &variables).ok().unwrap(), 2.0 * 2.0_f64.sin());
+        assert_eq!(calculator.calculate_with("2 x^2", &variables).ok().unwrap(), 8.0);
+        assert_eq!(calculator.calculate_with("1/2x", &variables).ok().unwrap(), 0.25);
+        assert_eq!(calculator.calculate_with("2-x", &variables).ok().unwrap(), 0.0);
+        assert_eq!(calculator.calculate_with("2 -x", &variables).ok().unwrap(), 0.0);
+
+        calculator.set_implicit_infix("*".to_string(), LOW_ORDER);
+        assert_eq!(calculator.calculate_with("1/2x", &variables).ok().unwrap(), 1.0);
+    }
+
+    #[test]
+    #[cfg(feature = "f64_calculator")]
+    fn it_calculates_extended_functions() {
+        use crate::f64_calculator::f64_calculator;
+        use std::f64::consts::PI;
+
+        let calculator = f64_calculator();
+        let close = |input: &str, expected: f64| {
+            let result = calculator.calculate(input).ok().unwrap();
+            assert!((result - expected).abs() < 1e-9, "{} = {}, expected {}", input, result, expected);
+        };
+
+        close("tan(pi/4)", 1.0);
+        close("asin 1", PI / 2.0);
+        close("atanh(tanh 0.5)", 0.5);
+        close("abs(-2) + floor 2.7 + ceil 2.1 + round 2.5 + trunc(-2.7)", 8.0);
+        close("sign(-3) + cbrt 27", 2.0);
+        close("5!", 120.0);
+        close("0.5!", PI.sqrt() / 2.0);
+        close("-3! + 2^3!", 58.0);
+        close("7 % 3 + -7 % 3", 3.0);
+        close("log(2, 8) + log10 100", 5.0);
+        close("min(2, 3) + max(2, 3) + hypot(3, 4)", 10.0);
+        close("atan2(1, 1)", PI / 4.0);
+        close("tau / 2 - pi", 0.0);
+        close("phi^2 - phi", 1.0);
+
+        assert!(calculator.calculate("inf").ok().unwrap().is_infinite());
+        assert!(calculator.calculate("nan").ok().unwrap().is_nan());
+        assert!(calculator.calculate("(-1)!").ok().unwrap().is_nan());
+    }
+
+    #[test]
+    #[cfg(feature = "f64_calculator")]
+    fn it_uses_angle_mode() {
+        use crate::f64_calculator::{f64_calculator, f64_calculator_with, AngleMode};
+
+        let radians = f64_calculator();
+        let degrees = f64_calculator_with(AngleMode::Degrees);
+        let close = |calculator: &crate::declarator::ExpressionDeclarator<f64>, input: &str, expected: f64| {
+            let result = calculator.calculate(input).ok().unwrap();
+            assert!((result - expected).abs() < 1e-9, "{} = {}, expected {}", input, result, expected);
+        };
+
+        close(&degrees, "sin 30 + cos 60 + tan 45", 2.0);
+        close(&degrees, "asin 1 + acos 1 + atan2(1, 1)", 135.0);
+        close(&degrees, "sin((pi/2) rad) + 180°", 181.0);
+        close(&radians, "sin(90°) + cos(pi rad)", 0.0);
+        close(&radians, "asin 1 - 90°", 0.0);
+        close(&radians, "-90°", -std::f64::consts::FRAC_PI_2);
+
+        let description = |calculator: &crate::declarator::ExpressionDeclarator<f64>| {
+            calculator.operations().iter().find(|it| it.signature == "sin").unwrap().description()
+        };
+        assert_eq!(description(&radians), "Sine, angle in radians");
+        assert_eq!(description(&degrees), "Sine, angle in degrees");
+    }
+
+    #[test]
+    #[cfg(feature = "f64_calculator")]
+    fn it_formats_numbers() {
+        use crate::f64_calculator::f64_calculator;
+        use crate::f64_calculator::format::{F64Formatter, Notation};
+
+        let calculator = f64_calculator();
+        let default = F64Formatter::new();
+        assert_eq!(default.format(calculator.calculate("0.1+0.2").ok().unwrap()), "0.3");
+        assert_eq!(default.format(calculator.calculate("1/3").ok().unwrap()), "0.333333333333333");
+        assert_eq!(default.format(-1234567.0), "-1234567");
+        assert_eq!(default.format(1e-7), "0.0000001");
+        assert_eq!(default.format(-0.0), "0");
+        assert_eq!(default.format(f64::NAN), "NaN");
+
+        let decimals = F64Formatter::new().with_decimals(2).with_thousands_separator(',');
+        assert_eq!(decimals.format(1234567.891), "1,234,567.89");
+        assert_eq!(decimals.format(0.5), "0.50");
+        assert_eq!(decimals.format(-999.999), "-1,000.00");
+
+        let european = F64Formatter::new().with_decimals(1).with_thousands_separator('.').with_decimal_separator(',');
+        assert_eq!(european.format(12345.67), "12.345,7");
+
+        let significant = F64Formatter::new().with_significant_digits(3);
+        assert_eq!(significant.format(123456.0), "123000");
+        assert_eq!(significant.format(0.00123456), "0.00123");
+        assert_eq!(significant.format(9.999), "10");
+
+        let scientific = F64Formatter::new().with_notation(Notation::Scientific).with_significant_digits(4);
+        assert_eq!(scientific.format(123456.0), "1.235e5");
+        assert_eq!(scientific.format(-0.00012), "-1.2e-4");
+        assert_eq!(scientific.format(0.0), "0e0");
+
+        let engineering = F64Formatter::new().with_notation(Notation::Engineering).with_decimals(2);
+        assert_eq!(engineering.format(123456.0), "123.46e3");
+        assert_eq!(engineering.format(0.0042), "4.20e-3");
+        assert_eq!(engineering.format(999.999), "1.00e3");
+        assert_eq!(engineering.format(99999.0), "100.00e3");
+
+        let rendering = F64Formatter::new().with_nan("n/a").with_infinity("∞", "-∞");
+        assert_eq!(rendering.format(f64::NAN), "n/a");
+        assert_eq!(rendering.format(f64::INFINITY), "∞");
+        assert_eq!(rendering.format(f64::NEG_INFINITY), "-∞");
+    }
+
+    #[test]
+    #[cfg(feature = "f64_calculator")]
+    fn it_parses_localized_numbers() {
+        use crate::f64_calculator::locale::{Grouping, Locale};
+        use crate::f64_calculator::{f64_calculator_with_locale, AngleMode};
+
+        let european = f64_calculator_with_locale(Locale::european(), AngleMode::Radians);
+        assert_eq!(european.calculate("3,5 + 1.234,5").ok().unwrap(), 1238.0);
+        assert_eq!(european.calculate("max(1,5; 2,5)").ok().unwrap(), 2.5);
+        assert_eq!(european.calculate("1.000.000").ok().unwrap(), 1e6);
+        assert_eq!(european.calculate("12.34").err().unwrap().get_value(), "12.34".to_string());
+        assert!(european.calculate("3.14").is_err());
+
+        let english = f64_calculator_with_locale(Locale::english(), AngleMode::Radians);
+        assert_eq!(english.calculate("1,234.5 * 2").ok().unwrap(), 2469.0);
+        assert_eq!(english.calculate("min(1; 2)").ok().unwrap(), 1.0);
+        assert!(english.calculate("1,23").is_err());
+
+        let indian = f64_calculator_with_locale(Locale::english().with_grouping(Grouping::Indian), AngleMode::Radians);
+        assert_eq!(indian.calculate("12,34,567.5").ok().unwrap(), 1234567.5);
+        assert!(indian.calculate("1,234,567").is_err());
+
+        let plain = f64_calculator_with_locale(Locale::new(',', None), AngleMode::Radians);
+        assert_eq!(plain.calculate("2,5e2").ok().unwrap(), 250.0);
+    }
+
+    #[test]
+    #[cfg(feature = "f64_calculator")]
+    fn it_parses_literal_syntax() {
+        use crate::f64_calculator::f64_calculator;
+        use crate::f64_calculator::locale::{Grouping, Locale};
+        use crate::f64_calculator::{f64_calculator_with_locale, AngleMode, F64Handler};
+
+        let calculator = f64_calculator();
+        assert_eq!(calculator.calculate(".5 + 5.").ok().unwrap(), 5.5);
+        assert_eq!(calculator.calculate("1_000_000").ok().unwrap(), 1e6);
+        assert_eq!(calculator.calculate("5.e2 + .5e1 + 1E-1").ok().unwrap(), 505.1);
+        assert_eq!(calculator.calculate("0xff + 0x1.8p3 + 0X.8").ok().unwrap(), 267.5);
+        assert_eq!(calculator.calculate("0x1p-2").ok().unwrap(), 0.25);
+        assert!(calculator.calculate("-INF").ok().unwrap().is_infinite());
+        assert!(calculator.calculate("Infinity").ok().unwrap().is_infinite());
+        assert!(calculator.calculate("NaN").ok().unwrap().is_nan());
+
+        assert!(calculator.calculate("1__0").is_err());
+        assert!(calculator.calculate("_1").is_err());
+        assert!(calculator.calculate("1.2.3").is_err());
+        assert!(calculator.calculate("0x").is_err());
+        assert!(calculator.calculate("1e").is_err());
+
+        let european = f64_calculator_with_locale(Locale::european(), AngleMode::Radians);
+        assert!(european.calculate(",5 + 1.000_000,25").is_err());
+        assert_eq!(european.calculate(",5 + 1.000,25 + 0x1,8").ok().unwrap(), 1002.25);
+        assert!(calculator.calculate("0x1p99999999999").ok().unwrap().is_infinite());
+        assert_eq!(calculator.calculate("0x0p99999999999 + 0x1p-99999999999").ok().unwrap(), 0.0);
+
+        // Literal is scanned once, the default scan checks every prefix
+        struct Prefixes(F64Handler);
+        impl PrimitiveHandler<f64> for Prefixes {
+            fn from_string(&self, input: &String) -> Result<f64, ()> {
+                return self.0.from_string(input);
+            }
+
+            fn can_start_with(&self, input: String) -> bool {
+                return self.0.can_start_with(input);
+            }
+        }
+        let inputs = ["1,234,567.5e-3x", "12,34,567,8", "1,23", "1.e+", "0x1.8p3+", "0x_1", "infinit", "Infinity1", "nan", "1_0_", ".5,5", "7e1_0"];
+        for locale in [Locale::default(), Locale::english(), Locale::european(), Locale::english().with_grouping(Grouping::Indian)] {
+            let handler = F64Handler::with_locale(locale.clone());
+            let prefixes = Prefixes(F64Handler::with_locale(locale));
+            for input in inputs.iter() {
+                let chars: Vec<char> = input.chars().collect();
+                let (found, reach) = handler.longest(&chars);
+                let (expected, expected_reach) = prefixes.longest(&chars);
+                assert_eq!(reach, expected_reach, "{}", input);
+                assert_eq!(found.map(|(length, val)| (length, val.to_bits())), expected.map(|(length, val)| (length, val.to_bits())), "{}", input);
+            }
+        }
+        let long = "1".repeat(100_000);
+        assert_eq!(calculator.tokenize(&long).ok().unwrap().len(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "mixed_calculator")]
+    fn it_records_spans_of_unicode_input() {
+        use crate::f64_calculator::add_unicode_aliases;
+        use crate::mixed_calculator::{mixed_calculator, Value};
+        use crate::token::Span;
+
+        let mut calculator = mixed_calculator();
+        add_unicode_aliases(&mut calculator);
+        calculator.add_alias("=\u{338}".to_string(), "!=".to_string());
+
+        assert_eq!(calculator.calculate("2 × π ÷ π − √4").ok().unwrap(), Value::Number(0.0));
+        assert_eq!(calculator.calculate("1 ≤ 2 & 3 ≠ 4 & 5 =\u{338} 6").ok().unwrap(), Value::Bool(true));
+
+        let input = "√4 × x";
+        let tokens = calculator.tokenize("√4 × 1").ok().unwrap();
+        let spans: Vec<Span> = tokens.iter().map(|it| it.get_span()).collect();
+        assert_eq!(spans[2], Span { byte_start: 4, byte_end: 5, char_start: 2, char_end: 3, line: 1, column: 3 });
+        assert_eq!(spans[3].text(input), "×");
+        assert_eq!(tokens[3].get_pos(), 3);
+
+        let error = calculator.calculate(input).err().unwrap();
+        assert_eq!(error.get_span(), Span { byte_start: 8, byte_end: 9, char_start: 5, char_end: 6, line: 1, column: 6 });
+        assert_eq!(error.get_span().text(input), "x");
+
+        let error = calculator.calculate("1 =\u{338}\u{338} 2").err().unwrap();
+        assert_eq!(error.get_span().char_start, 4);
+    }
+
+    #[test]
+    #[cfg(feature = "f64_calculator")]
+    fn it_accepts_any_whitespace() {
+        use crate::f64_calculator::f64_calculator;
+        use crate::token::Token;
+
+        let calculator = f64_calculator();
+        let input = "1 +\t2\n*\u{a0}3\r\n\u{2003}- 4";
+        assert_eq!(calculator.calculate(input).ok().unwrap(), 3.0);
+
+        let tokens = calculator.tokenize(input).ok().unwrap();
+        let whitespace: Vec<String> = tokens.iter()
+            .filter(|it| matches!(it, Token::WhiteSpace { .. }))
+            .map(|it| it.get_value())
+            .collect();
+        assert_eq!(whitespace, vec![" ", "\t", "\n", "\u{a0}", "\r\n\u{2003}", " "]);
+        let text: String = tokens.iter().map(|it| it.get_value()).collect();
+        assert_eq!(text, input);
+
+        let error = calculator.calculate("1 +\n  2 +\n\t@").err().unwrap();
+        let span = error.get_span();
+        assert_eq!((span.line, span.column, span.char_start), (3, 2, 11));
+        assert_eq!(error.pretty_print(), "'@' at line 3, column 2");
+        assert_eq!(calculator.calculate("1 + @").err().unwrap().pretty_print(), "'@' at position 4");
+        assert_eq!(calculator.calculate("1 + @\n+ 2").err().unwrap().pretty_print(), "'@' at position 4");
+    }
+
+    #[test]
+    #[cfg(feature = "f64_calculator")]
+    fn it_ignores_comments() {
+        use crate::f64_calculator::f64_calculator;
+        use crate::token::Token;
+
+        let mut calculator = f64_calculator();
+        let input = "2 * # double\n(3 + 1)#";
+        assert_eq!(calculator.calculate(input).ok().unwrap(), 8.0);
+        let tokens = calculator.tokenize(input).ok().unwrap();
+        let comments: Vec<String> = tokens.iter()
+            .filter(|it| matches!(it, Token::Comment { .. }))
+            .map(|it| it.get_value())
+            .collect();
+        assert_eq!(comments, vec!["# double", "#"]);
+        assert_eq!(tokens.iter().map(|it| it.get_value()).collect::<String>(), input);
+
+        assert!(calculator.calculate("4 /* half */ 2").is_err());
+        calculator.add_block_comment("/*".to_string(), "*/".to_string());
+        calculator.add_line_comment("//".to_string());
+        assert_eq!(calculator.calculate("4 / /* half */ 2").ok().unwrap(), 2.0);
+        assert_eq!(calculator.calculate("4/2// half").ok().unwrap(), 2.0);
+        assert_eq!(calculator.calculate("2/*\n*/-1").ok().unwrap(), 1.0);
+
+        let error = calculator.calculate("1 + /* 2").err().unwrap();
+        assert_eq!((error.get_value(), error.get_pos()), ("/*".to_string(), 4));
+    }
+
+    #[test]
+    #[cfg(feature = "mixed_calculator")]
+    fn it_tokenizes_by_longest_match() {
+        use crate::declarator::LOW_ORDER;
+        use crate::f64_calculator::f64_calculator;
+        use crate::mixed_calculator::{mixed_calculator, Value};
+        use std::collections::HashMap;
+        use std::f64::consts::E;
+
+        let mut calculator = f64_calculator();
+        calculator.add_variable("x".to_string());
+        let mut variables = HashMap::new();
+        variables.insert("x".to_string(), 8.0);
+
+        let values: Vec<String> = calculator.tokenize("log2x+log10 x+log(2,x)").ok().unwrap().iter().map(|it| it.get_value()).collect();
+        assert_eq!(values, vec!["log2", "x", "+", "log10", " ", "x", "+", "log", "(", "2", ",", "x", ")"]);
+        assert_eq!(calculator.calculate_with("log2x + log(2, x)", &variables).ok().unwrap(), 6.0);
+        assert_eq!(calculator.calculate("exp 0 + e").ok().unwrap(), 1.0 + E);
+
+        calculator.set_implicit_infix("*".to_string(), LOW_ORDER);
+        assert_eq!(calculator.calculate("2e").ok().unwrap(), 2.0 * E);
+        assert_eq!(calculator.calculate_with("ex", &variables).ok().unwrap(), 8.0 * E);
+        assert_eq!(calculator.calculate("2e1").ok().unwrap(), 20.0);
+
+        let calculator = mixed_calculator();
+        assert_eq!(calculator.calculate("1<-2").ok().unwrap(), Value::Bool(false));
+        assert_eq!(calculator.calculate("1<=-2").ok().unwrap(), Value::Bool(false));
+        assert_eq!(calculator.calculate("true<->false").ok().unwrap(), Value::Bool(false));
+        assert_eq!(calculator.calculate("nan != nan").ok().unwrap(), Value::Bool(true));
+        assert_eq!(calculator.calculate("true nand false").ok().unwrap(), Value::Bool(true));
+        assert_eq!(calculator.calculate("3!=6").ok().unwrap(), Value::Bool(true));
+        assert_eq!(calculator.calculate("3! == 6").ok().unwrap(), Value::Bool(true));
+
+        let error = calculator.calculate("1 + 2_").err().unwrap();
+        assert_eq!((error.get_value(), error.get_pos()), ("2_".to_string(), 4));
+    }
+
+    #[test]
+    #[cfg(feature = "f64_calculator")]
+    fn it_looks_up_signatures_among_many_operations() {
+        use crate::calculator::Thunk;
+        use crate::f64_calculator::f64_calculator;
+
+        let mut calculator = f64_calculator();
+        for i in 0..1000 {
+            calculator.add_function(format!("f{}", i), "Multiply by index".to_string(), Box::new(move |ops: &[f64]| ops[0] * i as f64), 1);
+        }
+        calculator.add_ternary("f?".to_string(), "f:".to_string(), "Condition".to_string(), Box::new(|thunks: &[Thunk<f64>]| {
+            return if thunks[0]()? != 0.0 { thunks[1]() } else { thunks[2]() };
+        }), LOW_ORDER);
+
+        assert_eq!(calculator.operations().len(), 1000 + f64_calculator().operations().len() + 1);
+        assert_eq!(calculator.calculate("f1(2) + f10(2) + f999 2").ok().unwrap(), 2.0 + 20.0 + 1998.0);
+        assert_eq!(calculator.calculate("1 f? f2(3) f: f3(3)").ok().unwrap(), 6.0);
+        assert_eq!(calculator.calculate("-f5(1)").ok().unwrap(), -5.0);
+    }
+
+    #[test]
+    #[cfg(feature = "f64_calculator")]
+    fn it_shares_operations_between_tokens() {
+        use crate::ast::AstNode;
+        use crate::f64_calculator::f64_calculator;
+        use crate::token::Token;
+        use std::sync::Arc;
+
+        let calculator = f64_calculator();
+        let tokens = calculator.tokenize("1 + 2 + 3").ok().unwrap();
+        let plus = calculator.operations().iter().find(|it| it.signature == "+").unwrap();
+        let shared = tokens.iter().filter(|it| match it {
+            Token::Operation { val, .. } => Arc::ptr_eq(val, plus),
+            _ => false,
+        }).count();
+        assert_eq!(shared, 2);
+
+        match &calculator.build_ast_from_tokens(&tokens).ok().unwrap() {
+            AstNode::Binary { op, .. } => assert!(Arc::ptr_eq(op, plus)),
+            _ => panic!("Binary node expected"),
+        }
+
+        let input = vec!["2 * 3 - 5"; 200].join(" + ");
+        assert_eq!(calculator.tokenize(&input).ok().unwrap().len(), 200 * 9 + 199 * 3);
+        assert_eq!(calculator.calculate(&input).ok().unwrap(), 200.0);
+    }
+
+    #[test]
+    #[cfg(feature = "mixed_calculator")]
+    fn it_calculates_deeply_nested_expressions() {
+        use crate::bool_calculator::boolean_calculator;
+        use crate::declarator::DEFAULT_MAX_DEPTH;
+        use crate::f64_calculator::f64_calculator;
+        use crate::token::Token;
+
+        let calculator = f64_calculator();
+        let sum = vec!["1"; 100_000].join("+");
+        let ast = calculator.build_ast(&sum).ok().unwrap();
+        assert_eq!(ast.depth(), 100_000);
+        assert_eq!(ast.clone().calculate().ok().unwrap(), 100_000.0);
+        assert_eq!(ast.pretty_print(), vec!["1"; 100_000].join(" + "));
+
+        let nested = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+        assert_eq!(calculator.calculate(&nested).ok().unwrap(), 1.0);
+        let negated = format!("{}1", "-".repeat(100_000));
+        assert_eq!(calculator.calculate(&negated).ok().unwrap(), 1.0);
+        assert_eq!(calculator.build_ast(&negated).ok().unwrap().pretty_print(), negated);
+
+        let mut calculator = boolean_calculator();
+        let conjunction = vec!["1"; DEFAULT_MAX_DEPTH + 2].join("&");
+        match calculator.build_ast(&conjunction).err().unwrap() {
+            Token::TooDeep { span, val } => {
+                assert_eq!(val, "&");
+                assert_eq!(span.char_start, 2 * DEFAULT_MAX_DEPTH + 1);
+            }
+            _ => panic!("TooDeep error expected"),
+        }
+
+        calculator.set_max_depth(10);
+        let conjunction = ["1"; 11].join("&");
+        assert_eq!(calculator.calculate(&conjunction).ok().unwrap(), true);
+        assert!(matches!(calculator.calculate(&format!("{}&1", conjunction)).err().unwrap(), Token::TooDeep { .. }));
+    }
+
+    #[test]
+    #[cfg(feature = "mixed_calculator")]
+    fn it_calculates_lazy_operations_at_default_depth() {
+        use crate::bool_calculator::boolean_calculator;
+        use crate::declarator::DEFAULT_MAX_DEPTH;
+        use crate::mixed_calculator::{mixed_calculator, Value};
+        use crate::token::Token;
+
+        let deepest = std::thread::Builder::new().stack_size(2 * 1024 * 1024).spawn(|| {
+            let calculator = boolean_calculator();
+            let conjunction = vec!["true"; DEFAULT_MAX_DEPTH + 1].join(" & ");
+            let ast = calculator.build_ast(&conjunction).ok().unwrap();
+            assert_eq!(ast.lazy_depth(), DEFAULT_MAX_DEPTH);
+            assert_eq!(ast.calculate().ok().unwrap(), true);
+            assert_eq!(calculator.build_flat_ast(&conjunction).ok().unwrap().calculate(&calculator).ok().unwrap(), true);
+            assert_eq!(calculator.compile(&ast).ok().unwrap().calculate(&calculator).ok().unwrap(), true);
+            let error = calculator.build_ast(&format!("{} & true", conjunction)).err().unwrap();
+            assert!(matches!(error, Token::TooDeep { .. }));
+
+            let calculator = mixed_calculator();
+            let conjunction = vec!["true"; DEFAULT_MAX_DEPTH + 1].join(" & ");
+            let conditional = format!("{}1", "false ? 0 : ".repeat(DEFAULT_MAX_DEPTH));
+            for input in [conjunction, conditional].iter() {
+                let ast = calculator.build_ast(input).ok().unwrap();
+                assert_eq!(ast.lazy_depth(), DEFAULT_MAX_DEPTH);
+                let expected = ast.calculate().ok().unwrap();
+                assert!(expected == Value::Bool(true) || expected == Value::Number(1.0));
+                assert_eq!(calculator.build_flat_ast(input).ok().unwrap().calculate(&calculator).ok().unwrap(), expected);
+                assert_eq!(calculator.compile(&ast).ok().unwrap().calculate(&calculator).ok().unwrap(), expected);
+            }
+        });
+        deepest.unwrap().join().unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "mixed_calculator")]
+    fn it_builds_flat_ast() {
+        use crate::declarator::LOW_ORDER;
+        use crate::f64_calculator::f64_calculator;
+        use crate::flat_ast::{FlatAst, FlatNode};
+        use crate::mixed_calculator::{mixed_calculator, Value};
+        use std::collections::HashMap;
+
+        let mut calculator = f64_calculator();
+        calculator.add_variable("x".to_string());
+        calculator.set_implicit_infix("*".to_string(), LOW_ORDER + 5);
+        let mut variables = HashMap::new();
+        variables.insert("x".to_string(), 2.0);
+
+        let input = "max(1, 2x) + sin(pi / 2) - 3! / x";
+        let flat = calculator.build_flat_ast(input).ok().unwrap();
+        let ast = calculator.build_ast(input).ok().unwrap();
+        assert_eq!(flat.calculate_with(&calculator, &variables).ok().unwrap(), ast.calculate_with(&variables).ok().unwrap());
+        assert_eq!(flat.calculate_with(&calculator, &variables).ok().unwrap(), 2.0);
+        assert_eq!(flat.node_count(), 15);
+        assert_eq!(flat.variables(), vec!["x"]);
+        assert_eq!(flat.root() as usize, flat.node_count() - 1);
+        match flat.node(flat.root()) {
+            FlatNode::Operation { op, operands, .. } => {
+                assert_eq!(calculator.operations()[*op as usize].signature, "-");
+                assert_eq!(*operands, 2);
+            }
+            _ => panic!("Operation node expected"),
+        }
+        assert_eq!(flat.token(flat.operands(flat.root())[1], &calculator).get_pos(), 30);
+
+        assert_eq!(flat.to_ast(&calculator).pretty_print(), ast.pretty_print());
+        let converted = FlatAst::from_ast(&ast, &calculator).ok().unwrap();
+        assert_eq!(converted.to_ast(&calculator).pretty_print(), flat.to_ast(&calculator).pretty_print());
+        assert_eq!(flat.calculate(&calculator).err().unwrap().get_value(), "x");
+
+        let mut extended = f64_calculator();
+        extended.add_function("twice".to_string(), "Double".to_string(), Box::new(|ops: &[f64]| ops[0] * 2.0), 1);
+        let foreign = extended.build_ast("1 + twice(2)").ok().unwrap();
+        assert_eq!(FlatAst::from_ast(&foreign, &calculator).err().unwrap().get_value(), "twice");
+        let foreign = extended.build_ast("1 + 2").ok().unwrap();
+        assert_eq!(FlatAst::from_ast(&foreign, &calculator).err().unwrap().get_value(), "+");
+        assert_eq!(calculator.compile(&foreign).err().unwrap().get_value(), "+");
+
+        let calculator = mixed_calculator();
+        let flat = calculator.build_flat_ast("1 < 2 ? 10 : 1 / 0").ok().unwrap();
+        assert_eq!(flat.calculate(&calculator).ok().unwrap(), Value::Number(10.0));
+        assert_eq!(flat.clone().to_ast(&calculator).calculate().ok().unwrap(), Value::Number(10.0));
+    }
+
+    #[test]
+    #[cfg(feature = "mixed_calculator")]
+    fn it_compiles_to_bytecode() {
+        use crate::bytecode::{BatchVm, Instruction, Vm};
+        use crate::calculator::Thunk;
+        use crate::operation::OperationType;
+        use crate::f64_calculator::f64_calculator;
+        use crate::mixed_calculator::{mixed_calculator, Value};
+        use std::collections::HashMap;
+
+        let mut calculator = f64_calculator();
+        calculator.add_variable("x".to_string());
+        calculator.add_variable("y".to_string());
+        let ast = calculator.build_ast("2 * x + max(x, y) - pi").ok().unwrap();
+        let program = calculator.compile(&ast).ok().unwrap();
+        assert_eq!(program.variables(), vec!["x", "y"]);
+        assert_eq!(program.code().len(), 9);
+        assert_eq!(program.code()[0], Instruction::Push(0));
+        assert_eq!(program.code()[1], Instruction::Load(0));
+        let id = |signature: &str| calculator.operations().iter().position(|it| it.signature == signature && it.operands == 2).unwrap();
+        assert_eq!(
+            program.disassemble(&calculator),
+            [
+                "0  push 0           ; 2".to_string(),
+                "1  load 0           ; x".to_string(),
+                format!("2  call {:<12}; x*y", format!("{}/2", id("*"))),
+                "3  load 0           ; x".to_string(),
+                "4  load 1           ; y".to_string(),
+                format!("5  call {:<12}; max(x, y)", format!("{}/2", id("max"))),
+                format!("6  call {:<12}; x+y", format!("{}/2", id("+"))),
+                "7  push 1           ; pi".to_string(),
+                format!("8  call {:<12}; x-y", format!("{}/2", id("-"))),
+            ].join("\n"),
+        );
+
+        let mut variables = HashMap::new();
+        variables.insert("x".to_string(), 3.0);
+        variables.insert("y".to_string(), 5.0);
+        let expected = ast.calculate_with(&variables).ok().unwrap();
+        assert_eq!(program.calculate_with(&calculator, &variables).ok().unwrap(), expected);
+        assert_eq!(program.calculate(&calculator).err().unwrap().get_value(), "x");
+
+        let mut vm = Vm::new();
+        assert_eq!(vm.run(&program, &calculator, &[]).err().unwrap().get_value(), "x");
+        assert_eq!(vm.run(&program, &calculator, &[1.0]).err().unwrap().get_value(), "y");
+        for x in 0..10 {
+            let result = vm.run(&program, &calculator, &[x as f64, 1.0]).ok().unwrap();
+            assert_eq!(result, 2.0 * x as f64 + (x as f64).max(1.0) - std::f64::consts::PI);
+        }
+
+        let calculator = mixed_calculator();
+        let ast = calculator.build_ast("1 < 2 ? 10 : 1 / 0 + (true ? 1 : 2)").ok().unwrap();
+        let program = calculator.compile(&ast).ok().unwrap();
+        assert_eq!(program.calculate(&calculator).ok().unwrap(), Value::Number(10.0));
+        assert!(matches!(program.code()[0], Instruction::Jump(_)));
+        assert!(program.disassemble(&calculator).lines().last().unwrap().contains("lazy"));
+
+        let mut eager = ExpressionDeclarator::<i32>::new(Box::new(IntHandler {}));
+        eager.add_infix("+".to_string(), "+".to_string(), Box::new(|op1, op2| { op1 + op2 }), LOW_ORDER);
+        let mut lazy = ExpressionDeclarator::<i32>::new(Box::new(IntHandler {}));
+        lazy.add_lazy(
+            "+".to_string(),
+            "+".to_string(),
+            OperationType::Infix,
+            Box::new(|ops: &[Thunk<i32>]| { Result::Ok(ops[0]()? + ops[1]()?) }),
+            2,
+            LOW_ORDER,
+        );
+        let program = eager.compile(&eager.build_ast("1 + 2").ok().unwrap()).ok().unwrap();
+        assert_eq!(program.calculate(&lazy).err().unwrap().get_value(), "+");
+        assert_eq!(BatchVm::new().run(&program, &lazy, &[], &mut [0]).err().unwrap().get_value(), "+");
+    }
+
+    /// System allocator, which counts allocations of the current thread
+    #[cfg(feature = "f64_calculator")]
+    struct CountingAllocator;
+
+    #[cfg(feature = "f64_calculator")]
+    thread_local! {
+        static ALLOCATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    #[cfg(feature = "f64_calculator")]
+    unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|it| it.set(it.get() + 1));
+            return std::alloc::System.alloc(layout);
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            std::alloc::System.dealloc(ptr, layout);
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: std::alloc::Layout, new_size: usize) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|it| it.set(it.get() + 1));
+            return std::alloc::System.realloc(ptr, layout, new_size);
+        }
+    }
+
+    #[cfg(feature = "f64_calculator")]
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    #[test]
+    #[cfg(feature = "f64_calculator")]
+    fn it_runs_vm_without_allocations() {
+        use crate::bytecode::Vm;
+        use crate::f64_calculator::f64_calculator;
+
+        let mut calculator = f64_calculator();
+        calculator.add_variable("x".to_string());
+        calculator.add_variable("y".to_string());
+        calculator.add_function("sum3".to_string(), "Sum of three".to_string(), Box::new(|ops: &[f64]| ops.iter().sum()), 3);
+        let program = calculator.compile(&calculator.build_ast("2 * x + max(x, y) - sum3(x, y, 1) / sin(-y)").ok().unwrap()).ok().unwrap();
+
+        let mut vm = Vm::new();
+        let first = vm.run(&program, &calculator, &[0.0, 1.0]).ok().unwrap();
+        let before = ALLOCATIONS.with(|it| it.get());
+        let mut total = 0.0;
+        for x in 0..1000 {
+            total += vm.run(&program, &calculator, &[x as f64, 1.0]).ok().unwrap();
+        }
+        assert_eq!(ALLOCATIONS.with(|it| it.get()), before);
+        assert_eq!(first, 1.0 + 2.0 / 1f64.sin());
+        assert!(total.is_finite());
+    }
+
+    #[test]
+    #[cfg(feature = "mixed_calculator")]
+    fn it_calculates_columns() {
+        use crate::bytecode::{BatchVm, BATCH_SIZE};
+        use crate::f64_calculator::f64_calculator;
+        use crate::mixed_calculator::{mixed_calculator, Value};
+        use crate::operation::OperationType;
+        use std::collections::HashMap;
+
+        let mut calculator = f64_calculator();
+        calculator.add_variable("x".to_string());
+        calculator.add_variable("y".to_string());
+        calculator.add_prefix("twice".to_string(), "Twice without vectorized executor".to_string(), Box::new(|op1| { op1 * 2.0 }), HIGH_ORDER);
+        let ast = calculator.build_ast("-x * 2 + max(x, y) ^ 2 % 7 - sin(y) / (1 + twice(x))").ok().unwrap();
+        let program = calculator.compile(&ast).ok().unwrap();
+
+        let rows = BATCH_SIZE * 2 + 3;
+        let xs: Vec<f64> = (0..rows).map(|it| it as f64 * 0.5).collect();
+        let ys: Vec<f64> = (0..rows).map(|it| (rows - it) as f64 * 0.25).collect();
+        let mut columns: HashMap<String, &[f64]> = HashMap::new();
+        columns.insert("x".to_string(), &xs);
+        columns.insert("y".to_string(), &ys);
+        let mut out = vec![0.0; rows];
+        program.calculate_columns(&calculator, &columns, &mut out).ok().unwrap();
+        for row in 0..rows {
+            let mut variables = HashMap::new();
+            variables.insert("x".to_string(), xs[row]);
+            variables.insert("y".to_string(), ys[row]);
+            assert_eq!(out[row], ast.calculate_with(&variables).ok().unwrap());
+        }
+
+        columns.remove("y");
+        assert_eq!(program.calculate_columns(&calculator, &columns, &mut out).err().unwrap().get_value(), "y");
+
+        calculator.set_vectorized("twice".to_string(), OperationType::Prefix, Box::new(|args: &[&[f64]], out: &mut [f64]| {
+            for (result, x) in out.iter_mut().zip(args[0]) {
+                *result = x * 3.0;
+            }
+        }));
+        let ast = calculator.build_ast("twice x").ok().unwrap();
+        let program = calculator.compile(&ast).ok().unwrap();
+        let mut out = vec![0.0; 3];
+        BatchVm::new().run(&program, &calculator, &[&[1.0, 2.0, 3.0]], &mut out).ok().unwrap();
+        assert_eq!(out, vec![3.0, 6.0, 9.0]);
+        assert_eq!(BatchVm::new().run(&program, &calculator, &[], &mut out).err().unwrap().get_value(), "x");
+        assert_eq!(BatchVm::new().run(&program, &calculator, &[&[1.0, 2.0]], &mut out).err().unwrap().get_value(), "x");
+
+        let mut calculator = mixed_calculator();
+        calculator.add_variable("n".to_string());
+        let ast = calculator.build_ast("n > 0 ? 10 / n : -1").ok().unwrap();
+        let program = calculator.compile(&ast).ok().unwrap();
+        let ns = [Value::Number(2.0), Value::Number(0.0), Value::Number(5.0)];
+        let mut out = vec![Value::Number(0.0); 3];
+        BatchVm::new().run(&program, &calculator, &[&ns], &mut out).ok().unwrap();
+        assert_eq!(out, vec![Value::Number(5.0), Value::Number(-1.0), Value::Number(2.0)]);
+    }
+
+    #[test]
+    #[cfg(all(feature = "parallel", feature = "f64_calculator"))]
+    fn it_calculates_in_parallel() {
+        use crate::calculator::Thunk;
+        use crate::f64_calculator::f64_calculator;
+        use crate::operation::OperationType;
+        use crate::token::{Span, Token};
+        use std::collections::HashMap;
+
+        fn shareable<X: Send + Sync>(_: &X) {}
+
+        let mut calculator = f64_calculator();
+        calculator.add_variable("x".to_string());
+        calculator.add_variable("y".to_string());
+        shareable::<ExpressionDeclarator<f64>>(&calculator);
+        let ast = calculator.build_ast("sin(x) ^ 2 + y / (1 + x)").ok().unwrap();
+        let program = calculator.compile(&ast).ok().unwrap();
+
+        let bindings: Vec<Vec<f64>> = (0..1000).map(|it| vec![it as f64 * 0.01, (it % 7) as f64]).collect();
+        let expected: Vec<f64> = bindings.iter().map(|set| {
+            let mut variables = HashMap::new();
+            variables.insert("x".to_string(), set[0]);
+            variables.insert("y".to_string(), set[1]);
+            ast.calculate_with(&variables).ok().unwrap()
+        }).collect();
+        for threads in [0, 1, 3, 8, 2000] {
+            assert_eq!(program.calculate_parallel(&calculator, &bindings, threads).ok().unwrap(), expected);
+        }
+        assert!(program.calculate_parallel(&calculator, &[], 4).ok().unwrap().is_empty());
+
+        calculator.add_lazy(
+            "positive".to_string(),
+            "Fails on negative values".to_string(),
+            OperationType::Prefix,
+            Box::new(|ops: &[Thunk<f64>]| {
+                let value = ops[0]()?;
+                if value < 0.0 {
+                    return Result::Err(Token::Unknown { span: Span::default(), val: value.to_string() });
+                }
+                Result::Ok(value)
+            }),
+            1,
+            HIGH_ORDER,
+        );
+        let ast = calculator.build_ast("positive(x) + y").ok().unwrap();
+        let program = calculator.compile(&ast).ok().unwrap();
+        let mut bindings: Vec<Vec<f64>> = (0..1000).map(|it| vec![it as f64, 1.0]).collect();
+        bindings[700][0] = -700.0;
+        bindings[5][0] = -5.0;
+        for threads in [1, 2, 8] {
+            assert_eq!(program.calculate_parallel(&calculator, &bindings, threads).err().unwrap().get_value(), "-5");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "mixed_calculator")]
+    fn it_eliminates_common_subexpressions() {
+        use crate::ast::AstNode;
+        use crate::f64_calculator::f64_calculator;
+        use crate::flat_ast::FlatAst;
+        use crate::mixed_calculator::{mixed_calculator, Value};
+        use crate::operation::OperationType;
+        use std::collections::{HashMap, HashSet};
+        use std::sync::atomic::{AtomicI32, Ordering};
+        use std::sync::Arc;
+
+        let calls = Arc::new(AtomicI32::new(0));
+        let counter = calls.clone();
+        let mut calculator = f64_calculator();
+        calculator.add_variable("x".to_string());
+        calculator.add_prefix(
+            "count".to_string(),
+            "Count calls".to_string(),
+            Box::new(move |op1| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                op1
+            }),
+            HIGH_ORDER,
+        );
+
+        let ast = calculator.build_ast("sin(x)^2 + sin(x)*cos(x)").ok().unwrap();
+        match &ast {
+            AstNode::Binary { p1, p2, .. } => match (&**p1, &**p2) {
+                (AstNode::Binary { p1: left, .. }, AstNode::Binary { p1: right, p2: cos, .. }) => {
+                    assert!(left == right);
+                    assert!(left != cos);
+                    let mut set = HashSet::new();
+                    set.insert(&**left);
+                    assert!(set.contains(&**right));
+                    assert!(!set.contains(&**cos));
+                }
+                _ => panic!("Binary nodes expected"),
+            },
+            _ => panic!("Binary node expected"),
+        }
+        assert!(ast == calculator.build_ast("sin x ^ 2 + (sin(x) * cos(x))").ok().unwrap());
+        assert!(ast != calculator.build_ast("sin(x)^2.0 + sin(x)*cos(x)").ok().unwrap());
+        assert!(ast != calculator.build_ast("sin(x)^2 + sin(x)*cos(-x)").ok().unwrap());
+        assert!(calculator.build_ast("pi - e").ok().unwrap() != calculator.build_ast("e - pi").ok().unwrap());
+
+        let dag = calculator.eliminate_common_subexpressions(&ast).ok().unwrap();
+        assert_eq!(dag.node_count(), 7);
+        assert_eq!(FlatAst::from_ast(&ast, &calculator).ok().unwrap().node_count(), 10);
+        assert!(dag.to_ast(&calculator) == ast);
+        let mut variables = HashMap::new();
+        variables.insert("x".to_string(), 0.5);
+        assert_eq!(dag.calculate_with(&calculator, &variables).ok().unwrap(), ast.calculate_with(&variables).ok().unwrap());
+
+        let ast = calculator.build_ast("count(x + 1) * count(x + 1) + count(x + 1)").ok().unwrap();
+        let dag = calculator.eliminate_common_subexpressions(&ast).ok().unwrap();
+        assert_eq!(dag.node_count(), 6);
+        assert_eq!(dag.calculate_with(&calculator, &variables).ok().unwrap(), 3.75);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(dag.calculate_with(&calculator, &variables).ok().unwrap(), 3.75);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let built_before = calculator.build_ast("count(x) + count(x)").ok().unwrap();
+        calculator.set_impure("count".to_string(), OperationType::Prefix);
+        assert_eq!(calculator.eliminate_common_subexpressions(&built_before).ok().unwrap().node_count(), 4);
+        let ast = calculator.build_ast("count(x + 1) * count(x + 1) + count(x + 1)").ok().unwrap();
+        let dag = calculator.eliminate_common_subexpressions(&ast).ok().unwrap();
+        assert_eq!(dag.node_count(), 8);
+        assert_eq!(dag.calculate_with(&calculator, &variables).ok().unwrap(), 3.75);
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+
+        let mut calculator = mixed_calculator();
+        calculator.add_variable("x".to_string());
+        let ast = calculator.build_ast("(x > 1 ? x * 2 : 0) + x * 2").ok().unwrap();
+        let dag = calculator.eliminate_common_subexpressions(&ast).ok().unwrap();
+        for (x, expected) in [(3.0, 12.0), (0.5, 1.0)] {
+            let mut variables = HashMap::new();
+            variables.insert("x".to_string(), Value::Number(x));
+            assert_eq!(dag.calculate_with(&calculator, &variables).ok().unwrap(), Value::Number(expected));
+        }
+    }
+}
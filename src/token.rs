@@ -1,60 +1,142 @@
-use crate::operation::Operation;
+use crate::operation::{Operation, OperationType};
+use std::sync::Arc;
+
+/// Location of token in input. Ends are exclusive.
+/// Byte offsets can be used to slice input, char offsets to point at the text for user.
+/// Line and column of the start are counted from 1 in chars, lines are separated by '\n'.
+/// Tokens, which are not present in input, have default span with zero line.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Span {
+    pub byte_start: usize,
+    pub byte_end: usize,
+    pub char_start: usize,
+    pub char_end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// Text of the span in input, which was tokenized
+    pub fn text<'a>(&self, input: &'a str) -> &'a str {
+        return &input[self.byte_start..self.byte_end];
+    }
+
+    /// Empty span at the start of this one, e.g. for tokens which are not present in input
+    pub fn start_point(&self) -> Span {
+        return Span { byte_end: self.byte_start, char_end: self.char_start, ..*self };
+    }
+}
 
 pub enum Token<T: Clone> {
-    WhiteSpace { pos: usize, val: String },
-    Open { pos: usize },
-    Close { pos: usize },
-    Primitive { pos: usize, val: T, original: String },
-    Operation { pos: usize, val: Box<Operation<T>> },
-    Unknown { pos: usize, val: String },
+    WhiteSpace { span: Span, val: String },
+    /// Comment with its markers, e.g. "# note" or "/* note */"
+    Comment { span: Span, val: String },
+    Open { span: Span },
+    Close { span: Span },
+    /// Separator of function arguments or delimiter of ternary operation
+    Separator { span: Span, val: String },
+    Primitive { span: Span, val: T, original: String },
+    /// Operation is shared with the declarator, so tokens are cheap to clone
+    Operation { span: Span, val: Arc<Operation<T>> },
+    Variable { span: Span, val: String },
+    Unknown { span: Span, val: String },
+    /// Lazy operation, which nests deeper than the maximum depth of the declarator
+    TooDeep { span: Span, val: String },
 }
 
 impl<T: 'static + Clone> Clone for Token<T> {
     fn clone(&self) -> Self {
         return match self {
-            Token::WhiteSpace { pos, val } => Token::WhiteSpace { pos: *pos, val: val.clone() },
-            Token::Open { pos } => Token::Open { pos: *pos },
-            Token::Close { pos } => Token::Close { pos: *pos },
-            Token::Primitive { pos, val, original } => Token::Primitive { pos: *pos, val: val.clone(), original: original.clone() },
-            Token::Operation { pos, val } => Token::Operation { pos: *pos, val: val.clone() },
-            Token::Unknown { pos, val } => Token::Unknown { pos: *pos, val: val.clone() },
+            Token::WhiteSpace { span, val } => Token::WhiteSpace { span: *span, val: val.clone() },
+            Token::Comment { span, val } => Token::Comment { span: *span, val: val.clone() },
+            Token::Open { span } => Token::Open { span: *span },
+            Token::Close { span } => Token::Close { span: *span },
+            Token::Separator { span, val } => Token::Separator { span: *span, val: val.clone() },
+            Token::Primitive { span, val, original } => Token::Primitive { span: *span, val: val.clone(), original: original.clone() },
+            Token::Operation { span, val } => Token::Operation { span: *span, val: val.clone() },
+            Token::Variable { span, val } => Token::Variable { span: *span, val: val.clone() },
+            Token::Unknown { span, val } => Token::Unknown { span: *span, val: val.clone() },
+            Token::TooDeep { span, val } => Token::TooDeep { span: *span, val: val.clone() },
         };
     }
 }
 
 impl<T: 'static + Clone> Token<T> {
+    /// Char offset of the token start
     pub fn get_pos(&self) -> usize {
+        return self.get_span().char_start;
+    }
+
+    pub fn get_span(&self) -> Span {
         return match self {
-            Token::WhiteSpace { pos, .. } => *pos,
-            Token::Open { pos } => *pos,
-            Token::Close { pos } => *pos,
-            Token::Primitive { pos, .. } => *pos,
-            Token::Operation { pos, .. } => *pos,
-            Token::Unknown { pos, .. } => *pos,
+            Token::WhiteSpace { span, .. } => *span,
+            Token::Comment { span, .. } => *span,
+            Token::Open { span } => *span,
+            Token::Close { span } => *span,
+            Token::Separator { span, .. } => *span,
+            Token::Primitive { span, .. } => *span,
+            Token::Operation { span, .. } => *span,
+            Token::Variable { span, .. } => *span,
+            Token::Unknown { span, .. } => *span,
+            Token::TooDeep { span, .. } => *span,
         };
     }
 
     pub fn get_value(&self) -> String {
+        return self.text().to_string();
+    }
+
+    /// String value without copy
+    pub(crate) fn text(&self) -> &str {
         return match self {
-            Token::WhiteSpace { pos: _pos, val } => val.clone(),
-            Token::Open { .. } => "(".to_string(),
-            Token::Close { .. } => ")".to_string(),
-            Token::Primitive { pos: _pos, val: _val, original } => original.clone(),
-            Token::Operation { pos: _pos, val } => val.signature.clone(),
-            Token::Unknown { pos: _pos, val } => val.clone()
+            Token::WhiteSpace { val, .. } => val,
+            Token::Comment { val, .. } => val,
+            Token::Open { .. } => "(",
+            Token::Close { .. } => ")",
+            Token::Separator { val, .. } => val,
+            Token::Primitive { original, .. } => original,
+            Token::Operation { val, .. } => &val.signature,
+            Token::Variable { val, .. } => val,
+            Token::Unknown { val, .. } => val,
+            Token::TooDeep { val, .. } => val,
         };
     }
 }
 
 impl<T: 'static + Clone> Token<T> {
+    /// Token with position on the first line of input and with line and column after it,
+    /// e.g. "'x' at position 4" or "'x' at line 2, column 3"
     pub fn pretty_print(&self) -> String {
+        let span = self.get_span();
+        if span.line > 1 {
+            return format!("'{}' at line {}, column {}", self.get_value(), span.line, span.column);
+        }
+
+        return format!("'{}' at position {}", self.get_value(), span.char_start);
+    }
+}
+
+impl<T: 'static + Clone> Token<T> {
+    /// Token is not a part of expression, e.g. whitespace or comment
+    pub(crate) fn is_ignored(&self) -> bool {
+        return matches!(self, Token::WhiteSpace { .. } | Token::Comment { .. });
+    }
+
+    /// Token can be the last one of an operand, e.g. `2`, `pi`, `x!` or `)`
+    pub(crate) fn ends_operand(&self) -> bool {
         return match self {
-            Token::WhiteSpace { pos, val } => format!("'{}' at position {}", val, pos),
-            Token::Open { pos } => format!("'(' at position {}", pos),
-            Token::Close { pos } => format!("')' at position {}", pos),
-            Token::Primitive { pos, val: _val, original } => format!("'{}' at position {}", original, pos),
-            Token::Operation { pos, val } => format!("'{}' at position {}", val.signature.to_string(), pos),
-            Token::Unknown { pos, val } => format!("'{}' at position {}", val, pos),
+            Token::Close { .. } | Token::Primitive { .. } | Token::Variable { .. } => true,
+            Token::Operation { val, .. } => matches!(val.op_type, OperationType::Constant | OperationType::Postfix),
+            _ => false,
         };
     }
-}
\ No newline at end of file
+
+    /// Token can be the first one of an operand, e.g. `2`, `pi`, `sin` or `(`
+    pub(crate) fn starts_operand(&self) -> bool {
+        return match self {
+            Token::Open { .. } | Token::Primitive { .. } | Token::Variable { .. } => true,
+            Token::Operation { val, .. } => matches!(val.op_type, OperationType::Constant | OperationType::Prefix),
+            _ => false,
+        };
+    }
+}
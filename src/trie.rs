@@ -0,0 +1,96 @@
+use crate::operation::OperationType;
+use std::collections::HashMap;
+
+/// Prefix tree of signatures and delimiters of operations.
+/// Lookups cost depends on the length of text only, not on the number of operations.
+pub(crate) struct SignatureTrie {
+    nodes: Vec<Node>,
+}
+
+#[derive(Default)]
+struct Node {
+    children: HashMap<char, usize>,
+    /// Types and indices of operations, which signature ends at the node, in order of declaration
+    operations: Vec<(OperationType, usize)>,
+    /// Delimiter of ternary operation ends at the node
+    delimiter: bool,
+}
+
+impl SignatureTrie {
+    pub(crate) fn new() -> SignatureTrie {
+        return SignatureTrie { nodes: vec![Node::default()] };
+    }
+
+    pub(crate) fn add_operation(&mut self, signature: &str, op_type: OperationType, index: usize) {
+        let node = self.add(signature);
+        self.nodes[node].operations.push((op_type, index));
+    }
+
+    pub(crate) fn add_delimiter(&mut self, delimiter: &str) {
+        let node = self.add(delimiter);
+        self.nodes[node].delimiter = true;
+    }
+
+    /// Types and indices of operations with signature
+    pub(crate) fn operations(&self, signature: &str) -> &[(OperationType, usize)] {
+        return match self.find(signature) {
+            Some(node) => &self.nodes[node].operations,
+            None => &[],
+        };
+    }
+
+    /// Index of the first declared operation with signature and type
+    pub(crate) fn operation(&self, signature: &str, op_type: &OperationType) -> Option<usize> {
+        return self.operations(signature).iter().find(|(it, _)| it == op_type).map(|(_, index)| *index);
+    }
+
+    pub(crate) fn is_delimiter(&self, text: &str) -> bool {
+        return self.find(text).is_some_and(|node| self.nodes[node].delimiter);
+    }
+
+    /// Lengths of signatures and delimiters at the start of chars from the shortest to the longest,
+    /// and the length of the longest text, which can start one
+    pub(crate) fn matches(&self, chars: &[char]) -> (Vec<usize>, usize) {
+        let mut found = Vec::new();
+        let mut node = 0;
+        let mut reach = 0;
+        for char in chars {
+            node = match self.nodes[node].children.get(char) {
+                Some(next) => *next,
+                None => break,
+            };
+            reach += 1;
+            if self.nodes[node].delimiter || !self.nodes[node].operations.is_empty() {
+                found.push(reach);
+            }
+        }
+
+        return (found, reach);
+    }
+
+    fn find(&self, text: &str) -> Option<usize> {
+        let mut node = 0;
+        for char in text.chars() {
+            node = *self.nodes[node].children.get(&char)?;
+        }
+
+        return Some(node);
+    }
+
+    fn add(&mut self, text: &str) -> usize {
+        let mut node = 0;
+        for char in text.chars() {
+            node = match self.nodes[node].children.get(&char) {
+                Some(next) => *next,
+                None => {
+                    self.nodes.push(Node::default());
+                    let next = self.nodes.len() - 1;
+                    self.nodes[node].children.insert(char, next);
+                    next
+                }
+            };
+        }
+
+        return node;
+    }
+}